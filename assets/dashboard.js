@@ -33,9 +33,11 @@ function formatLine(event) {
   const ts = event.ts || '';
   const method = event.method ? ` ${event.method}` : '';
   const hash = event.request_hash ? ` ${event.request_hash.slice(0, 10)}…` : '';
+  const pool = event.pool ? ` @${event.pool}` : '';
   const latency = event.latency_ms != null ? ` ${event.latency_ms}ms` : '';
+  const ttl = event.ttl_ms != null ? ` ttl=${event.ttl_ms}ms` : '';
   const note = event.note ? ` :: ${event.note}` : '';
-  return `${ts} [${event.event}] ${event.level}${method}${hash}${latency}${note}`;
+  return `${ts} [${event.event}] ${event.level}${method}${pool}${hash}${latency}${ttl}${note}`;
 }
 
 function matchesFilters(event) {
@@ -0,0 +1,547 @@
+//! Account-change invalidation: `accountSubscribe` for hot cached accounts.
+//!
+//! Cached `getAccountInfo` / `getBalance` responses register their account
+//! here. A background task keeps one upstream subscription per account and
+//! commitment of the cached reads, up to a cap with least-recently-used
+//! accounts dropped first, and removes every cache entry of an account when it
+//! changes. Entries for accounts that are not (or no longer) watched are
+//! removed right away, so a long TTL never serves an unwatched account.
+
+use crate::cache::Cache;
+use crate::commitment::Commitment;
+use crate::log_events::{LogEvent, LogState};
+use futures_util::stream::SplitSink;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+const COMMAND_BUFFER: usize = 4096;
+
+type Sink = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
+
+/// Whether cached responses of `method` are tied to an account subscription.
+pub fn is_watched_method(method: &str) -> bool {
+    matches!(method, "getAccountInfo" | "getBalance")
+}
+
+/// Account read by a cached call of a watched method.
+pub fn watched_account<'a>(method: &str, payload: &'a Value) -> Option<&'a str> {
+    if !is_watched_method(method) {
+        return None;
+    }
+    payload.pointer("/params/0")?.as_str()
+}
+
+/// An account as seen at one commitment level; changes are only notified at
+/// the level they were subscribed at.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Watched {
+    account: String,
+    commitment: Commitment,
+}
+
+enum Command {
+    /// A response for `account` was cached under `key`.
+    Watch { account: Watched, key: String },
+    /// A cached response for `account` was served.
+    Touch { account: Watched },
+}
+
+#[derive(Clone)]
+pub struct AccountWatcher {
+    commands: mpsc::Sender<Command>,
+}
+
+impl AccountWatcher {
+    pub fn spawn(
+        cache: Arc<dyn Cache>,
+        log_state: Arc<LogState>,
+        ws_url: String,
+        limit: usize,
+    ) -> Self {
+        let (commands, receiver) = mpsc::channel(COMMAND_BUFFER);
+        tokio::spawn(run(cache, log_state, ws_url, limit.max(1), receiver));
+        Self { commands }
+    }
+
+    /// Registers `key`, a read of `account` at `commitment` that was just cached.
+    pub fn watch(&self, account: &str, commitment: Commitment, key: &str) {
+        self.send(Command::Watch {
+            account: Watched {
+                account: account.to_string(),
+                commitment,
+            },
+            key: key.to_string(),
+        });
+    }
+
+    pub fn touch(&self, account: &str, commitment: Commitment) {
+        self.send(Command::Touch {
+            account: Watched {
+                account: account.to_string(),
+                commitment,
+            },
+        });
+    }
+
+    fn send(&self, command: Command) {
+        // Never block the request path; a dropped touch only affects eviction order.
+        if let Err(mpsc::error::TrySendError::Full(Command::Watch { key, .. })) =
+            self.commands.try_send(command)
+        {
+            tracing::warn!(hash = %key, "account watcher backlog full, entry left to its TTL");
+        }
+    }
+}
+
+struct Tracked {
+    keys: HashSet<String>,
+    subscription: Option<u64>,
+    last_used: u64,
+}
+
+/// Subscriptions of one upstream connection.
+#[derive(Default)]
+struct Session {
+    accounts: HashMap<Watched, Tracked>,
+    // Request id -> account, until upstream confirms the subscription.
+    pending: HashMap<u64, Watched>,
+    subscriptions: HashMap<u64, Watched>,
+    next_id: u64,
+    clock: u64,
+}
+
+async fn run(
+    cache: Arc<dyn Cache>,
+    log_state: Arc<LogState>,
+    ws_url: String,
+    limit: usize,
+    mut commands: mpsc::Receiver<Command>,
+) {
+    let mut reconnect_delay = MIN_RECONNECT_DELAY;
+
+    loop {
+        let reason = match connect_async(&ws_url).await {
+            Ok((socket, _)) => {
+                reconnect_delay = MIN_RECONNECT_DELAY;
+                tracing::info!("account watcher connected");
+                log_state
+                    .record(LogEvent::new("INFO", "ACCOUNT_STREAM_UP"))
+                    .await;
+                watch_accounts(cache.as_ref(), socket, limit, &mut commands).await
+            }
+            // Connection errors can echo the URL, which carries the QuickNode token.
+            Err(_) => "failed to connect to upstream websocket".to_string(),
+        };
+        tracing::warn!(reason = %reason, "account watcher disconnected");
+        log_state
+            .record(LogEvent::new("WARN", "ACCOUNT_STREAM_LOST").with_note(reason))
+            .await;
+
+        // Nothing is watched while disconnected, so new entries are dropped at once.
+        let reconnect = sleep(reconnect_delay);
+        tokio::pin!(reconnect);
+        loop {
+            tokio::select! {
+                _ = &mut reconnect => break,
+                command = commands.recv() => match command {
+                    Some(Command::Watch { key, .. }) => {
+                        cache.remove(&key).await;
+                    }
+                    Some(Command::Touch { .. }) => {}
+                    None => return,
+                },
+            }
+        }
+        reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+/// Runs one connection until it fails; returns why it ended.
+async fn watch_accounts(
+    cache: &dyn Cache,
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    limit: usize,
+    commands: &mut mpsc::Receiver<Command>,
+) -> String {
+    let (mut sink, mut stream) = socket.split();
+    let mut session = Session::default();
+
+    let reason = loop {
+        tokio::select! {
+            command = commands.recv() => {
+                let Some(command) = command else {
+                    break "gateway shutting down".to_string();
+                };
+                if let Err(reason) = session.apply(cache, &mut sink, command, limit).await {
+                    break reason;
+                }
+            }
+            message = stream.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None => {
+                        break "upstream closed the websocket".to_string();
+                    }
+                    Some(Ok(_)) => continue,
+                    Some(Err(err)) => break format!("websocket error: {}", err),
+                };
+                let Ok(message) = serde_json::from_str::<Value>(&text) else {
+                    continue;
+                };
+                if let Err(reason) = session.handle_message(cache, &mut sink, message).await {
+                    break reason;
+                }
+            }
+        }
+    };
+
+    // Changes are no longer observed; drop everything this session protected.
+    for (_, tracked) in session.accounts.drain() {
+        for key in tracked.keys {
+            cache.remove(&key).await;
+        }
+    }
+    reason
+}
+
+impl Session {
+    async fn apply(
+        &mut self,
+        cache: &dyn Cache,
+        sink: &mut Sink,
+        command: Command,
+        limit: usize,
+    ) -> Result<(), String> {
+        self.clock += 1;
+        let now = self.clock;
+
+        match command {
+            Command::Touch { account } => {
+                if let Some(tracked) = self.accounts.get_mut(&account) {
+                    tracked.last_used = now;
+                }
+            }
+            Command::Watch { account, key } => {
+                if let Some(tracked) = self.accounts.get_mut(&account) {
+                    tracked.keys.insert(key);
+                    tracked.last_used = now;
+                    return Ok(());
+                }
+
+                if self.accounts.len() >= limit {
+                    self.evict_least_recently_used(cache, sink).await?;
+                }
+
+                self.next_id += 1;
+                let id = self.next_id;
+                let subscribe = json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "method": "accountSubscribe",
+                    "params": [
+                        account.account,
+                        { "encoding": "base64", "commitment": account.commitment.to_string() }
+                    ]
+                });
+                send(sink, subscribe).await?;
+                self.pending.insert(id, account.clone());
+                self.accounts.insert(
+                    account,
+                    Tracked {
+                        keys: HashSet::from([key]),
+                        subscription: None,
+                        last_used: now,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    async fn evict_least_recently_used(
+        &mut self,
+        cache: &dyn Cache,
+        sink: &mut Sink,
+    ) -> Result<(), String> {
+        let Some(account) = self
+            .accounts
+            .iter()
+            .min_by_key(|(_, tracked)| tracked.last_used)
+            .map(|(account, _)| account.clone())
+        else {
+            return Ok(());
+        };
+
+        let tracked = self.accounts.remove(&account).expect("tracked account");
+        for key in &tracked.keys {
+            cache.remove(key).await;
+        }
+        // A still pending subscription is cancelled once upstream confirms it.
+        if let Some(subscription) = tracked.subscription {
+            self.unsubscribe(sink, subscription).await?;
+        }
+        tracing::debug!(account = %account.account, "stopped watching account");
+        Ok(())
+    }
+
+    async fn unsubscribe(&mut self, sink: &mut Sink, subscription: u64) -> Result<(), String> {
+        self.subscriptions.remove(&subscription);
+        self.next_id += 1;
+        let unsubscribe = json!({
+            "jsonrpc": "2.0",
+            "id": self.next_id,
+            "method": "accountUnsubscribe",
+            "params": [subscription]
+        });
+        send(sink, unsubscribe).await
+    }
+
+    async fn handle_message(
+        &mut self,
+        cache: &dyn Cache,
+        sink: &mut Sink,
+        message: Value,
+    ) -> Result<(), String> {
+        if let Some(id) = message.get("id").and_then(Value::as_u64) {
+            // Unsubscribe confirmations have no pending entry and are ignored.
+            let Some(account) = self.pending.remove(&id) else {
+                return Ok(());
+            };
+            let subscription = message.get("result").and_then(Value::as_u64);
+            match (subscription, self.accounts.get_mut(&account)) {
+                (Some(subscription), Some(tracked)) => {
+                    tracked.subscription = Some(subscription);
+                    self.subscriptions.insert(subscription, account);
+                    // Changes before the subscription was live were never
+                    // notified, so reads cached until now may already be stale.
+                    for key in tracked.keys.drain() {
+                        cache.remove(&key).await;
+                    }
+                }
+                (None, Some(_)) => {
+                    // Upstream refused the subscription; the account cannot be watched.
+                    let tracked = self.accounts.remove(&account).expect("tracked account");
+                    for key in &tracked.keys {
+                        cache.remove(key).await;
+                    }
+                }
+                // Evicted while pending; upstream should not keep sending changes.
+                (Some(subscription), None) => self.unsubscribe(sink, subscription).await?,
+                (None, None) => {}
+            }
+            return Ok(());
+        }
+
+        if message.get("method").and_then(Value::as_str) != Some("accountNotification") {
+            return Ok(());
+        }
+        let account = message
+            .pointer("/params/subscription")
+            .and_then(Value::as_u64)
+            .and_then(|subscription| self.subscriptions.get(&subscription));
+        let Some(tracked) = account.and_then(|account| self.accounts.get_mut(account)) else {
+            return Ok(());
+        };
+
+        // The subscription stays; the next cached read registers its key again.
+        let mut removed = 0;
+        for key in tracked.keys.drain() {
+            removed += cache.remove(&key).await as usize;
+        }
+        tracing::debug!(removed, "account changed, invalidated cached reads");
+        Ok(())
+    }
+}
+
+async fn send(sink: &mut Sink, message: Value) -> Result<(), String> {
+    sink.send(Message::Text(message.to_string()))
+        .await
+        .map_err(|err| format!("websocket error: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{CacheLimits, MemoryCache};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+
+    const TTL: Duration = Duration::from_secs(600);
+
+    /// Fake upstream: confirms every request with an increasing subscription id
+    /// and forwards the requests it receives.
+    async fn spawn_account_upstream(received: mpsc::UnboundedSender<Value>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = accept_async(stream).await.unwrap();
+            let mut next_subscription = 0;
+            while let Some(Ok(Message::Text(text))) = socket.next().await {
+                let request: Value = serde_json::from_str(&text).unwrap();
+                received.send(request.clone()).unwrap();
+                next_subscription += 1;
+                let confirm =
+                    json!({ "jsonrpc": "2.0", "id": request["id"], "result": next_subscription });
+                socket
+                    .send(Message::Text(confirm.to_string()))
+                    .await
+                    .unwrap();
+            }
+        });
+        format!("ws://{}", addr)
+    }
+
+    fn cache() -> Arc<dyn Cache> {
+        Arc::new(MemoryCache::new(CacheLimits {
+            max_entries: 100,
+            max_bytes: usize::MAX,
+        }))
+    }
+
+    async fn wait_until_removed(cache: &dyn Cache, key: &str) {
+        for _ in 0..50 {
+            if cache.get(key).await.is_none() {
+                return;
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    #[test]
+    fn test_watched_account_reads_first_param() {
+        let read = json!({ "method": "getBalance", "params": ["addr", {}] });
+
+        assert_eq!(watched_account("getBalance", &read), Some("addr"));
+        assert_eq!(watched_account("getSlot", &read), None);
+    }
+
+    #[tokio::test]
+    async fn test_limit_drops_least_recently_used_account() {
+        let cache = cache();
+        let (received, mut requests) = mpsc::unbounded_channel();
+        let url = spawn_account_upstream(received).await;
+        let watcher =
+            AccountWatcher::spawn(cache.clone(), Arc::new(LogState::new(100, 16)), url, 1);
+
+        cache
+            .insert("a".to_string(), "getBalance", json!(1), TTL)
+            .await;
+        watcher.watch("alice", Commitment::Finalized, "a");
+        assert_eq!(requests.recv().await.unwrap()["method"], "accountSubscribe");
+
+        watcher.watch("bob", Commitment::Finalized, "b");
+        // Alice may still be pending, so her unsubscribe can follow Bob's subscribe.
+        let mut next = Vec::new();
+        for _ in 0..2 {
+            let request = requests.recv().await.unwrap();
+            next.push((request["method"].clone(), request["params"][0].clone()));
+        }
+        assert!(next.contains(&(json!("accountUnsubscribe"), json!(1))));
+        assert!(next.contains(&(json!("accountSubscribe"), json!("bob"))));
+
+        // Alice is no longer watched, so her cached balance cannot be trusted.
+        assert_eq!(cache.get("a").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_reads_cached_before_the_subscription_is_live_are_dropped() {
+        let cache = cache();
+        let (received, mut requests) = mpsc::unbounded_channel();
+        let url = spawn_account_upstream(received).await;
+        let watcher =
+            AccountWatcher::spawn(cache.clone(), Arc::new(LogState::new(100, 16)), url, 10);
+
+        cache
+            .insert("early".to_string(), "getBalance", json!(1), TTL)
+            .await;
+        watcher.watch("alice", Commitment::Confirmed, "early");
+        let subscribe = requests.recv().await.unwrap();
+        assert_eq!(subscribe["params"][1]["commitment"], "confirmed");
+
+        // Alice may have changed before upstream confirmed the subscription.
+        wait_until_removed(cache.as_ref(), "early").await;
+        assert_eq!(cache.get("early").await, None);
+
+        cache
+            .insert("late".to_string(), "getBalance", json!(2), TTL)
+            .await;
+        watcher.watch("alice", Commitment::Confirmed, "late");
+        // A finalized read of the same account needs a subscription of its own.
+        watcher.watch("alice", Commitment::Finalized, "final");
+        let subscribe = requests.recv().await.unwrap();
+        assert_eq!(subscribe["params"][1]["commitment"], "finalized");
+        assert_eq!(cache.get("late").await, Some(json!(2)));
+    }
+
+    #[tokio::test]
+    async fn test_notification_invalidates_account_entries() {
+        let cache = cache();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (notify, notified) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = accept_async(stream).await.unwrap();
+            let Some(Ok(Message::Text(text))) = socket.next().await else {
+                return;
+            };
+            let request: Value = serde_json::from_str(&text).unwrap();
+            let confirm = json!({ "jsonrpc": "2.0", "id": request["id"], "result": 9 });
+            socket
+                .send(Message::Text(confirm.to_string()))
+                .await
+                .unwrap();
+            notified.await.unwrap();
+            let notification = json!({
+                "jsonrpc": "2.0",
+                "method": "accountNotification",
+                "params": { "result": { "context": { "slot": 5 }, "value": null }, "subscription": 9 }
+            });
+            socket
+                .send(Message::Text(notification.to_string()))
+                .await
+                .unwrap();
+            std::future::pending::<()>().await;
+        });
+
+        let watcher =
+            AccountWatcher::spawn(cache.clone(), Arc::new(LogState::new(100, 16)), url, 10);
+        // The first read starts the subscription and is dropped once it is live.
+        cache
+            .insert("first".to_string(), "getBalance", json!(7), TTL)
+            .await;
+        watcher.watch("alice", Commitment::Finalized, "first");
+        wait_until_removed(cache.as_ref(), "first").await;
+
+        cache
+            .insert("info".to_string(), "getAccountInfo", json!({}), TTL)
+            .await;
+        cache
+            .insert("balance".to_string(), "getBalance", json!(7), TTL)
+            .await;
+        cache
+            .insert("other".to_string(), "getBalance", json!(3), TTL)
+            .await;
+        watcher.watch("alice", Commitment::Finalized, "info");
+        watcher.watch("alice", Commitment::Finalized, "balance");
+        sleep(Duration::from_millis(50)).await;
+        assert_eq!(cache.get("balance").await, Some(json!(7)));
+        notify.send(()).unwrap();
+
+        wait_until_removed(cache.as_ref(), "balance").await;
+        assert_eq!(cache.get("info").await, None);
+        assert_eq!(cache.get("balance").await, None);
+        assert_eq!(cache.get("other").await, Some(json!(3)));
+    }
+}
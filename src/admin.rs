@@ -0,0 +1,247 @@
+//! Cache administration endpoints, served only when `ADMIN_TOKEN` is set.
+
+use crate::cache_snapshot::{export_snapshot, import_snapshot, Snapshot};
+use crate::log_events::LogEvent;
+use crate::server::AppState;
+use axum::extract::{DefaultBodyLimit, Path, Request, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+pub fn admin_routes(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/admin/cache", get(stats_handler).delete(flush_handler))
+        .route(
+            "/admin/cache/entries/:hash",
+            get(entry_handler).delete(purge_entry_handler),
+        )
+        .route("/admin/cache/methods/:method", delete(purge_method_handler))
+        .route(
+            "/admin/cache/snapshot",
+            get(export_handler)
+                // A snapshot can hold the whole cache.
+                .merge(post(import_handler).layer(DefaultBodyLimit::disable())),
+        )
+        .route_layer(middleware::from_fn_with_state(state, require_token))
+}
+
+/// Rejects requests without `Authorization: Bearer <ADMIN_TOKEN>`.
+async fn require_token(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let given = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match (&state.config.admin_token, given) {
+        (Some(expected), Some(given)) if token_matches(expected, given) => next.run(request).await,
+        _ => (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "missing or invalid admin token" })),
+        )
+            .into_response(),
+    }
+}
+
+/// Compares digests so the time taken reveals nothing about the token.
+fn token_matches(expected: &str, given: &str) -> bool {
+    Sha256::digest(expected.as_bytes()) == Sha256::digest(given.as_bytes())
+}
+
+async fn stats_handler(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({
+        "cache": state.cache.stats().await,
+        "methods": state.cache.method_stats().await,
+    }))
+}
+
+async fn entry_handler(
+    State(state): State<AppState>,
+    Path(hash): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    match state.cache.lookup(&hash).await {
+        Some(hit) => Ok(Json(json!({
+            "request_hash": hash,
+            "stale_ms": hit.stale_for.map(|stale_for| stale_for.as_millis() as u64),
+            "value": hit.value,
+        }))),
+        None => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "no cache entry for this hash" })),
+        )),
+    }
+}
+
+async fn purge_entry_handler(
+    State(state): State<AppState>,
+    Path(hash): Path<String>,
+) -> Json<Value> {
+    let removed = state.cache.remove(&hash).await as usize;
+    tracing::info!(hash = %hash, removed, "admin purged cache entry");
+    record_purge(
+        &state,
+        LogEvent::new("INFO", "CACHE_PURGE").with_hash(hash),
+        removed,
+    )
+    .await;
+    Json(json!({ "removed": removed }))
+}
+
+async fn purge_method_handler(
+    State(state): State<AppState>,
+    Path(method): Path<String>,
+) -> Json<Value> {
+    let removed = state.cache.invalidate_methods(&[method.as_str()]).await;
+    tracing::info!(method = %method, removed, "admin purged cache entries by method");
+    record_purge(
+        &state,
+        LogEvent::new("INFO", "CACHE_PURGE").with_method(method),
+        removed,
+    )
+    .await;
+    Json(json!({ "removed": removed }))
+}
+
+async fn flush_handler(State(state): State<AppState>) -> Json<Value> {
+    let removed = state.cache.clear().await;
+    tracing::warn!(removed, "admin flushed the cache");
+    record_purge(&state, LogEvent::new("WARN", "CACHE_PURGE"), removed).await;
+    Json(json!({ "removed": removed }))
+}
+
+async fn export_handler(State(state): State<AppState>) -> Json<Snapshot> {
+    let snapshot = export_snapshot(&state).await;
+    tracing::info!(
+        entries = snapshot.entries.len(),
+        "admin exported cache snapshot"
+    );
+    state
+        .log_state
+        .record(
+            LogEvent::new("INFO", "CACHE_SNAPSHOT")
+                .with_note(format!("exported {} entries", snapshot.entries.len())),
+        )
+        .await;
+    Json(snapshot)
+}
+
+async fn import_handler(
+    State(state): State<AppState>,
+    Json(snapshot): Json<Snapshot>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let imported = import_snapshot(&state, snapshot)
+        .await
+        .map_err(|err| (StatusCode::CONFLICT, Json(json!({ "error": err }))))?;
+    tracing::info!(imported, "admin imported cache snapshot");
+    state
+        .log_state
+        .record(
+            LogEvent::new("INFO", "CACHE_SNAPSHOT")
+                .with_note(format!("imported {} entries", imported)),
+        )
+        .await;
+    Ok(Json(json!({ "imported": imported })))
+}
+
+async fn record_purge(state: &AppState, event: LogEvent, removed: usize) {
+    state
+        .log_state
+        .record(event.with_note(format!("admin purge: {} removed", removed)))
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::privacy_mode::PrivacyMode;
+    use crate::proxy::tests::test_state;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    async fn serve_admin(token: &str) -> (AppState, String) {
+        let mut state = test_state("http://127.0.0.1:1".to_string(), PrivacyMode::Strict);
+        let config = Config {
+            admin_token: Some(token.to_string()),
+            ..(*state.config).clone()
+        };
+        state.config = Arc::new(config);
+
+        let app = admin_routes(state.clone()).with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (state, url)
+    }
+
+    #[tokio::test]
+    async fn test_requests_without_the_token_are_rejected() {
+        let (_, url) = serve_admin("secret").await;
+        let client = reqwest::Client::new();
+
+        let missing = client.get(format!("{}/admin/cache", url)).send().await;
+        assert_eq!(missing.unwrap().status(), 401);
+        let wrong = client
+            .delete(format!("{}/admin/cache", url))
+            .bearer_auth("guess")
+            .send()
+            .await;
+        assert_eq!(wrong.unwrap().status(), 401);
+    }
+
+    #[tokio::test]
+    async fn test_purges_are_applied_and_logged() {
+        let (state, url) = serve_admin("secret").await;
+        let client = reqwest::Client::new();
+        let ttl = Duration::from_secs(60);
+        for (key, method) in [("a", "getSlot"), ("b", "getBalance"), ("c", "getBalance")] {
+            state
+                .cache
+                .insert(key.to_string(), method, json!({"result": key}), ttl)
+                .await;
+        }
+        let call = |request: reqwest::RequestBuilder| async move {
+            let response = request.bearer_auth("secret").send().await.unwrap();
+            (response.status(), response.json::<Value>().await.unwrap())
+        };
+
+        let (_, stats) = call(client.get(format!("{}/admin/cache", url))).await;
+        assert_eq!(stats["cache"]["entries"], 3);
+        assert_eq!(stats["methods"]["getBalance"]["entries"], 2);
+
+        let (status, entry) = call(client.get(format!("{}/admin/cache/entries/a", url))).await;
+        assert_eq!(status, 200);
+        assert_eq!(entry["value"]["result"], "a");
+
+        let (_, purged) = call(client.delete(format!("{}/admin/cache/entries/a", url))).await;
+        assert_eq!(purged["removed"], 1);
+        let (status, _) = call(client.get(format!("{}/admin/cache/entries/a", url))).await;
+        assert_eq!(status, 404);
+
+        let (_, purged) =
+            call(client.delete(format!("{}/admin/cache/methods/getBalance", url))).await;
+        assert_eq!(purged["removed"], 2);
+
+        state
+            .cache
+            .insert("d".to_string(), "getSlot", json!({"result": "d"}), ttl)
+            .await;
+        let (_, purged) = call(client.delete(format!("{}/admin/cache", url))).await;
+        assert_eq!(purged["removed"], 1);
+        assert_eq!(state.cache.stats().await.entries, 0);
+
+        let events = state.log_state.recent(100).await;
+        let purges: Vec<_> = events
+            .iter()
+            .filter(|event| event.event == "CACHE_PURGE")
+            .collect();
+        assert_eq!(purges.len(), 3);
+        assert_eq!(purges[0].request_hash.as_deref(), Some("a"));
+        assert_eq!(purges[1].method.as_deref(), Some("getBalance"));
+        assert_eq!(purges[2].note.as_deref(), Some("admin purge: 1 removed"));
+    }
+}
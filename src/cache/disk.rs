@@ -0,0 +1,593 @@
+//! Disk-backed cache: the in-memory cache in front of one file per long-lived
+//! entry, so immutable data such as finalized blocks survives restarts.
+//!
+//! Only entries whose TTL reaches `min_ttl` are written out; short-lived reads
+//! like `getSlot` would churn the disk for no benefit. Files are indexed when
+//! the cache opens and read back lazily on a memory miss.
+
+use super::{
+    now_ms, Cache, CacheHit, CacheLimits, CacheStats, DiskStats, ExportedEntry, MemoryCache,
+    MethodStats,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Clone, Debug)]
+pub struct DiskConfig {
+    pub dir: PathBuf,
+    /// Budget for entry files; the entries closest to expiry are dropped first.
+    pub max_bytes: u64,
+    /// Shortest TTL worth persisting.
+    pub min_ttl: Duration,
+}
+
+pub struct DiskCache {
+    memory: MemoryCache,
+    config: DiskConfig,
+    stale_grace: Duration,
+    index: Mutex<DiskIndex>,
+    // Distinguishes temporary files of concurrent writes to the same key.
+    writes: AtomicU64,
+}
+
+#[derive(Default)]
+struct DiskIndex {
+    entries: HashMap<String, DiskEntry>,
+    bytes: u64,
+    /// Keys per method, so invalidation never walks the whole index.
+    by_method: HashMap<String, HashSet<String>>,
+}
+
+struct DiskEntry {
+    method: String,
+    /// Wall-clock end of the stale grace window in milliseconds since the Unix
+    /// epoch, valid across restarts.
+    expires_at: u64,
+    size: u64,
+}
+
+/// On-disk form of one entry.
+#[derive(Serialize, Deserialize)]
+struct StoredEntry {
+    key: String,
+    method: String,
+    /// End of the TTL; `expires_at` adds the stale grace window.
+    fresh_until: u64,
+    expires_at: u64,
+    value: Value,
+}
+
+impl DiskCache {
+    /// Opens `config.dir`, indexing the entries left by a previous run and
+    /// deleting those that expired or cannot be read. Files this cache did not
+    /// write are left alone.
+    pub fn open(
+        limits: CacheLimits,
+        stale_grace: Duration,
+        config: DiskConfig,
+    ) -> io::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+
+        let now = now_ms();
+        let mut index = DiskIndex::default();
+        let mut foreign = 0;
+        for file in fs::read_dir(&config.dir)? {
+            let path = file?.path();
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or("");
+            let (stem, extension) = name.split_once('.').unwrap_or((name, ""));
+            if !path.is_file() || !is_file_name_hash(stem) {
+                foreign += 1;
+                continue;
+            }
+            if let Some(write) = extension.strip_prefix("tmp") {
+                if !write.is_empty() && write.bytes().all(|b| b.is_ascii_digit()) {
+                    // Leftover temporary file from an interrupted write.
+                    let _ = fs::remove_file(&path);
+                } else {
+                    foreign += 1;
+                }
+                continue;
+            }
+            if extension != "json" {
+                foreign += 1;
+                continue;
+            }
+
+            let stored = fs::read(&path).ok().and_then(|bytes| {
+                Some((
+                    serde_json::from_slice::<StoredEntry>(&bytes).ok()?,
+                    bytes.len(),
+                ))
+            });
+            match stored {
+                // A copied or renamed entry would be indexed where `path` never looks.
+                Some((stored, _)) if file_name_hash(&stored.key) != stem => {
+                    foreign += 1;
+                }
+                Some((stored, size)) if stored.expires_at > now => {
+                    index.add(
+                        stored.key,
+                        DiskEntry {
+                            method: stored.method,
+                            expires_at: stored.expires_at,
+                            size: size as u64,
+                        },
+                    );
+                }
+                _ => {
+                    let _ = fs::remove_file(&path);
+                }
+            }
+        }
+        if foreign > 0 {
+            tracing::warn!(
+                files = foreign,
+                dir = %config.dir.display(),
+                "disk cache directory holds files it did not write, leaving them"
+            );
+        }
+        tracing::info!(
+            entries = index.entries.len(),
+            bytes = index.bytes,
+            dir = %config.dir.display(),
+            "opened disk cache"
+        );
+
+        Ok(Self {
+            memory: MemoryCache::new(limits).with_stale_grace(stale_grace),
+            config,
+            stale_grace,
+            index: Mutex::new(index),
+            writes: AtomicU64::new(0),
+        })
+    }
+
+    /// Compresses memory-tier entries of at least `min_bytes`; files stay plain JSON.
+    pub fn with_compression(mut self, min_bytes: Option<usize>) -> Self {
+        self.memory = self.memory.with_compression(min_bytes);
+        self
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.config
+            .dir
+            .join(format!("{}.json", file_name_hash(key)))
+    }
+
+    async fn read(&self, key: &str) -> Option<StoredEntry> {
+        let expires_at = {
+            let index = self.index.lock().expect("disk index lock");
+            index.entries.get(key)?.expires_at
+        };
+        if expires_at <= now_ms() {
+            self.remove_files(&[key.to_string()]).await;
+            return None;
+        }
+
+        let bytes = tokio::fs::read(self.path(key)).await.ok()?;
+        let stored: StoredEntry = serde_json::from_slice(&bytes).ok()?;
+        (stored.key == key).then_some(stored)
+    }
+
+    async fn write(&self, key: &str, method: &str, value: &Value, ttl: Duration) -> io::Result<()> {
+        let fresh_until = now_ms() + ttl.as_millis() as u64;
+        let stored = StoredEntry {
+            key: key.to_string(),
+            method: method.to_string(),
+            fresh_until,
+            expires_at: fresh_until + self.stale_grace.as_millis() as u64,
+            value: value.clone(),
+        };
+        let bytes = serde_json::to_vec(&stored)?;
+        let size = bytes.len() as u64;
+        if size > self.config.max_bytes {
+            self.remove_files(&[key.to_string()]).await;
+            return Ok(());
+        }
+
+        // Write then rename, so a crash never leaves a truncated entry behind.
+        let path = self.path(key);
+        let write = self.writes.fetch_add(1, Ordering::Relaxed);
+        let temporary = path.with_extension(format!("tmp{}", write));
+        tokio::fs::write(&temporary, &bytes).await?;
+        tokio::fs::rename(&temporary, &path).await?;
+
+        let evicted = {
+            let mut index = self.index.lock().expect("disk index lock");
+            index.take(key);
+            index.add(
+                key.to_string(),
+                DiskEntry {
+                    method: method.to_string(),
+                    expires_at: stored.expires_at,
+                    size,
+                },
+            );
+
+            let mut evicted = Vec::new();
+            while index.bytes > self.config.max_bytes {
+                let Some(oldest) = index
+                    .entries
+                    .iter()
+                    .filter(|(candidate, _)| candidate.as_str() != key)
+                    .min_by_key(|(_, entry)| entry.expires_at)
+                    .map(|(candidate, _)| candidate.clone())
+                else {
+                    break;
+                };
+                index.take(&oldest);
+                evicted.push(oldest);
+            }
+            evicted
+        };
+        for key in evicted {
+            let _ = tokio::fs::remove_file(self.path(&key)).await;
+        }
+        Ok(())
+    }
+
+    /// Drops `keys` from the index and deletes their files; returns how many were indexed.
+    async fn remove_files(&self, keys: &[String]) -> usize {
+        let removed: Vec<&String> = {
+            let mut index = self.index.lock().expect("disk index lock");
+            keys.iter()
+                .filter(|key| index.take(key).is_some())
+                .collect()
+        };
+        for key in &removed {
+            let _ = tokio::fs::remove_file(self.path(key)).await;
+        }
+        removed.len()
+    }
+
+    fn indexed_keys(&self, matches: impl Fn(&DiskEntry) -> bool) -> Vec<String> {
+        let index = self.index.lock().expect("disk index lock");
+        index
+            .entries
+            .iter()
+            .filter(|(_, entry)| matches(entry))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+}
+
+impl DiskIndex {
+    fn add(&mut self, key: String, entry: DiskEntry) {
+        self.bytes += entry.size;
+        self.by_method
+            .entry(entry.method.clone())
+            .or_default()
+            .insert(key.clone());
+        self.entries.insert(key, entry);
+    }
+
+    fn take(&mut self, key: &str) -> Option<DiskEntry> {
+        let entry = self.entries.remove(key)?;
+        self.bytes -= entry.size;
+        if let Some(keys) = self.by_method.get_mut(&entry.method) {
+            keys.remove(key);
+            if keys.is_empty() {
+                self.by_method.remove(&entry.method);
+            }
+        }
+        Some(entry)
+    }
+}
+
+#[async_trait]
+impl Cache for DiskCache {
+    async fn lookup(&self, key: &str) -> Option<CacheHit> {
+        if let Some(hit) = self.memory.lookup(key).await {
+            return Some(hit);
+        }
+
+        // Evicted from memory or written by an earlier run: promote it back while fresh.
+        let stored = self.read(key).await?;
+        let now = now_ms();
+        if now >= stored.fresh_until {
+            return Some(CacheHit {
+                value: stored.value,
+                stale_for: Some(Duration::from_millis(now - stored.fresh_until)),
+            });
+        }
+        let remaining = Duration::from_millis(stored.fresh_until - now);
+        self.memory
+            .insert(
+                key.to_string(),
+                &stored.method,
+                stored.value.clone(),
+                remaining,
+            )
+            .await;
+        Some(CacheHit {
+            value: stored.value,
+            stale_for: None,
+        })
+    }
+
+    async fn insert(&self, key: String, method: &str, value: Value, ttl: Duration) {
+        if ttl >= self.config.min_ttl {
+            if let Err(err) = self.write(&key, method, &value, ttl).await {
+                tracing::warn!(error = %err, hash = %key, "failed to persist cache entry");
+            }
+        } else {
+            // A persisted older answer must not outlive this one.
+            self.remove_files(std::slice::from_ref(&key)).await;
+        }
+        self.memory.insert(key, method, value, ttl).await;
+    }
+
+    async fn remove(&self, key: &str) -> bool {
+        let in_memory = self.memory.remove(key).await;
+        let on_disk = self.remove_files(&[key.to_string()]).await > 0;
+        in_memory || on_disk
+    }
+
+    async fn invalidate_methods(&self, methods: &[&str]) -> usize {
+        // Persisted keys first, so an entry held by both tiers is counted once.
+        let persisted: Vec<String> = {
+            let index = self.index.lock().expect("disk index lock");
+            methods
+                .iter()
+                .filter_map(|method| index.by_method.get(*method))
+                .flatten()
+                .cloned()
+                .collect()
+        };
+        for key in &persisted {
+            self.memory.remove(key).await;
+        }
+        let persisted = self.remove_files(&persisted).await;
+        persisted + self.memory.invalidate_methods(methods).await
+    }
+
+    async fn clear(&self) -> usize {
+        // Persisted keys first, so an entry held by both tiers is counted once.
+        let persisted = self.indexed_keys(|_| true);
+        for key in &persisted {
+            self.memory.remove(key).await;
+        }
+        let persisted = self.remove_files(&persisted).await;
+        persisted + self.memory.clear().await
+    }
+
+    async fn purge_expired(&self) -> usize {
+        let now = now_ms();
+        let expired = self.indexed_keys(|entry| entry.expires_at <= now);
+        self.remove_files(&expired).await;
+        // Persisted entries expire in memory at the same time, so they are counted there.
+        self.memory.purge_expired().await
+    }
+
+    async fn stats(&self) -> CacheStats {
+        let mut stats = self.memory.stats().await;
+        let index = self.index.lock().expect("disk index lock");
+        stats.disk = Some(DiskStats {
+            entries: index.entries.len(),
+            bytes: index.bytes,
+        });
+        stats
+    }
+
+    async fn method_stats(&self) -> BTreeMap<String, MethodStats> {
+        // Like `stats`, the counts describe the memory tier.
+        self.memory.method_stats().await
+    }
+
+    async fn export(&self) -> Vec<ExportedEntry> {
+        let mut exported = self.memory.export().await;
+        let in_memory: HashSet<String> = exported.iter().map(|entry| entry.key.clone()).collect();
+        let persisted = self.indexed_keys(|_| true);
+        let now = now_ms();
+        for key in persisted.into_iter().filter(|key| !in_memory.contains(key)) {
+            let Some(stored) = self.read(&key).await else {
+                continue;
+            };
+            if stored.fresh_until > now {
+                exported.push(ExportedEntry {
+                    key,
+                    method: stored.method,
+                    value: stored.value,
+                    ttl: Duration::from_millis(stored.fresh_until - now),
+                });
+            }
+        }
+        exported
+    }
+}
+
+/// File name stem for `key`. Keys are request hashes today, but hashing keeps
+/// any key a safe file name.
+fn file_name_hash(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// Whether `stem` has the shape of a `file_name_hash`.
+fn is_file_name_hash(stem: &str) -> bool {
+    stem.len() == 64 && stem.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::path::Path;
+
+    const LIMITS: CacheLimits = CacheLimits {
+        max_entries: 100,
+        max_bytes: usize::MAX,
+    };
+
+    fn json_files(dir: &Path) -> usize {
+        fs::read_dir(dir)
+            .unwrap()
+            .filter(|file| {
+                file.as_ref()
+                    .unwrap()
+                    .path()
+                    .extension()
+                    .is_some_and(|ext| ext == "json")
+            })
+            .count()
+    }
+
+    /// Fresh directory per test under the system temp dir.
+    fn config(test: &str, max_bytes: u64) -> DiskConfig {
+        let dir = std::env::temp_dir().join(format!("qn-gateway-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        DiskConfig {
+            dir,
+            max_bytes,
+            min_ttl: Duration::from_secs(60),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_long_lived_entries_survive_reopen() {
+        let config = config("reopen", u64::MAX);
+        let cache = DiskCache::open(LIMITS, Duration::ZERO, config.clone()).unwrap();
+        cache
+            .insert(
+                "block".to_string(),
+                "getBlock",
+                json!({"slot": 1}),
+                Duration::from_secs(3_600),
+            )
+            .await;
+        cache
+            .insert(
+                "tx".to_string(),
+                "getTransaction",
+                json!("tx"),
+                Duration::from_secs(3_600),
+            )
+            .await;
+        cache
+            .insert(
+                "slot".to_string(),
+                "getSlot",
+                json!(7),
+                Duration::from_secs(1),
+            )
+            .await;
+        assert!(cache.remove("tx").await);
+        drop(cache);
+
+        let cache = DiskCache::open(LIMITS, Duration::ZERO, config.clone()).unwrap();
+        assert_eq!(cache.get("block").await, Some(json!({"slot": 1})));
+        assert_eq!(cache.get("tx").await, None);
+        assert_eq!(cache.get("slot").await, None);
+        assert_eq!(cache.stats().await.disk.unwrap().entries, 1);
+
+        // The promoted copy and its file are one entry, found through the
+        // method index rebuilt on open.
+        assert_eq!(cache.invalidate_methods(&["getSlot"]).await, 0);
+        assert_eq!(cache.invalidate_methods(&["getBlock"]).await, 1);
+        assert_eq!(json_files(&config.dir), 0);
+        assert_eq!(cache.clear().await, 0);
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_expired_files_are_deleted_on_open() {
+        let mut config = config("expired", u64::MAX);
+        config.min_ttl = Duration::ZERO;
+        let cache = DiskCache::open(LIMITS, Duration::ZERO, config.clone()).unwrap();
+        cache
+            .insert(
+                "a".to_string(),
+                "getBlock",
+                json!(1),
+                Duration::from_millis(10),
+            )
+            .await;
+        assert_eq!(json_files(&config.dir), 1);
+        drop(cache);
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let cache = DiskCache::open(LIMITS, Duration::ZERO, config.clone()).unwrap();
+        assert_eq!(cache.get("a").await, None);
+        assert_eq!(json_files(&config.dir), 0);
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_open_only_removes_files_it_wrote() {
+        let config = config("foreign", u64::MAX);
+        let cache = DiskCache::open(LIMITS, Duration::ZERO, config.clone()).unwrap();
+        cache
+            .insert(
+                "block".to_string(),
+                "getBlock",
+                json!(1),
+                Duration::from_secs(3_600),
+            )
+            .await;
+        drop(cache);
+
+        let dir = &config.dir;
+        let entry = dir.join(format!("{}.json", file_name_hash("block")));
+        let copied = dir.join(format!("{}.json", file_name_hash("other")));
+        let leftover = dir.join(format!("{}.tmp3", file_name_hash("block")));
+        fs::copy(&entry, &copied).unwrap();
+        fs::write(&leftover, b"partial").unwrap();
+        fs::write(dir.join("notes.txt"), b"keep").unwrap();
+        fs::write(dir.join("settings.json"), b"{}").unwrap();
+
+        let cache = DiskCache::open(LIMITS, Duration::ZERO, config.clone()).unwrap();
+        assert_eq!(cache.stats().await.disk.unwrap().entries, 1);
+        assert!(!leftover.exists());
+        assert!(copied.exists());
+        assert!(dir.join("notes.txt").exists());
+        assert!(dir.join("settings.json").exists());
+
+        assert_eq!(cache.clear().await, 1);
+        assert!(!entry.exists());
+        assert!(copied.exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_disk_budget_drops_entries_closest_to_expiry() {
+        // Each stored entry is well under 100 bytes, so two fit and three do not.
+        let config = config("budget", 200);
+        let cache = DiskCache::open(LIMITS, Duration::ZERO, config.clone()).unwrap();
+        for (key, minutes) in [("a", 10), ("b", 5), ("c", 20)] {
+            cache
+                .insert(
+                    key.to_string(),
+                    "getBlock",
+                    json!(1),
+                    Duration::from_secs(minutes * 60),
+                )
+                .await;
+        }
+
+        let disk = cache.stats().await.disk.unwrap();
+        assert_eq!(disk.entries, 2);
+        assert!(disk.bytes <= 200);
+        assert_eq!(json_files(&config.dir), 2);
+        drop(cache);
+
+        let cache = DiskCache::open(LIMITS, Duration::ZERO, config.clone()).unwrap();
+        assert_eq!(cache.get("b").await, None);
+        assert_eq!(cache.get("a").await, Some(json!(1)));
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+}
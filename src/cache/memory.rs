@@ -0,0 +1,770 @@
+//! In-memory TTL cache for safe RPC responses, bounded by entry count and
+//! approximate size with least-recently-used eviction.
+//!
+//! Entries are spread over independently locked shards. Hits only take a shard's
+//! read lock and record recency in an atomic, so concurrent lookups never wait
+//! on each other; inserts and evictions lock a single shard for writing.
+//!
+//! Large responses can be kept as deflated JSON instead of a `Value` tree; they
+//! are compressed before the shard is locked and inflated after it is released.
+
+use super::{
+    Cache, CacheHit, CacheLimits, CacheStats, CompressionStats, ExportedEntry, MethodStats,
+};
+use async_trait::async_trait;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde_json::Value;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::BuildHasher;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Shard count for production caches.
+const SHARDS: usize = 16;
+
+pub struct MemoryCache {
+    limits: CacheLimits,
+    // Even split of the limits; a shard over its share evicts its own entries
+    // first, and borrows from the other shards only while it is within it.
+    shard_limits: CacheLimits,
+    // Entries and bytes over all shards, so the limits hold for the whole cache.
+    totals: Arc<Totals>,
+    shards: Box<[RwLock<Shard>]>,
+    hasher: RandomState,
+    /// How long entries stay servable as stale after their TTL.
+    stale_grace: Duration,
+    /// Serialized size from which entries are stored compressed.
+    compress_min_bytes: Option<usize>,
+    // Recency is measured from here; reading the clock needs no shared write.
+    epoch: Instant,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+#[derive(Default)]
+struct Totals {
+    entries: AtomicUsize,
+    bytes: AtomicUsize,
+}
+
+struct Shard {
+    totals: Arc<Totals>,
+    entries: HashMap<String, CacheEntry>,
+    /// Keys per method, so invalidation only touches that method's entries.
+    by_method: HashMap<String, HashSet<String>>,
+    bytes: usize,
+    // Compressed entries, their serialized size and what they take instead.
+    compressed: usize,
+    compressed_raw_bytes: usize,
+    compressed_bytes: usize,
+}
+
+struct CacheEntry {
+    /// RPC method of the cached call, used for invalidation.
+    method: String,
+    value: StoredValue,
+    expires_at: Instant,
+    /// End of the stale grace window; the entry is dropped after this.
+    stale_until: Instant,
+    size: usize,
+    // Nanoseconds since the cache epoch, updated under the read lock on every hit.
+    last_access: AtomicU64,
+}
+
+#[derive(Clone)]
+enum StoredValue {
+    Plain(Value),
+    /// Deflated JSON; shared so a hit only clones a pointer under the lock.
+    Compressed {
+        bytes: Arc<[u8]>,
+        raw_len: usize,
+    },
+}
+
+impl StoredValue {
+    fn decode(self) -> Option<Value> {
+        match self {
+            StoredValue::Plain(value) => Some(value),
+            StoredValue::Compressed { bytes, .. } => {
+                serde_json::from_reader(DeflateDecoder::new(&bytes[..])).ok()
+            }
+        }
+    }
+}
+
+impl MemoryCache {
+    pub fn new(limits: CacheLimits) -> Self {
+        Self::with_shards(limits, SHARDS)
+    }
+
+    fn with_shards(limits: CacheLimits, shards: usize) -> Self {
+        let shards = shards.max(1);
+        let totals = Arc::new(Totals::default());
+        Self {
+            limits,
+            shard_limits: CacheLimits {
+                max_entries: limits.max_entries.div_ceil(shards),
+                max_bytes: limits.max_bytes.div_ceil(shards),
+            },
+            shards: (0..shards)
+                .map(|_| RwLock::new(Shard::new(totals.clone())))
+                .collect(),
+            totals,
+            hasher: RandomState::new(),
+            stale_grace: Duration::ZERO,
+            compress_min_bytes: None,
+            epoch: Instant::now(),
+            evictions: AtomicU64::new(0),
+            expirations: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_stale_grace(mut self, grace: Duration) -> Self {
+        self.stale_grace = grace;
+        self
+    }
+
+    /// Compresses entries whose JSON is at least `min_bytes` long; `None`
+    /// keeps every entry as a `Value`.
+    pub fn with_compression(mut self, min_bytes: Option<usize>) -> Self {
+        self.compress_min_bytes = min_bytes;
+        self
+    }
+
+    /// The form `value` is kept in and its size in bytes.
+    fn encode(&self, value: Value) -> (StoredValue, usize) {
+        let raw = serde_json::to_vec(&value).unwrap_or_default();
+        let compressed = self
+            .compress_min_bytes
+            .filter(|&min_bytes| raw.len() >= min_bytes)
+            .and_then(|_| deflate(&raw));
+        match compressed {
+            Some(bytes) => {
+                let size = bytes.len();
+                let stored = StoredValue::Compressed {
+                    bytes: bytes.into(),
+                    raw_len: raw.len(),
+                };
+                (stored, size)
+            }
+            None => (StoredValue::Plain(value), raw.len()),
+        }
+    }
+
+    fn shard_index(&self, key: &str) -> usize {
+        self.hasher.hash_one(key) as usize % self.shards.len()
+    }
+
+    fn shard(&self, key: &str) -> &RwLock<Shard> {
+        &self.shards[self.shard_index(key)]
+    }
+
+    fn over_limits(&self) -> bool {
+        self.totals.entries.load(Ordering::Relaxed) > self.limits.max_entries
+            || self.totals.bytes.load(Ordering::Relaxed) > self.limits.max_bytes
+    }
+
+    /// Evicts least recently used entries of `shard` other than `keep` while
+    /// the cache is over its limits and `until` still holds.
+    fn evict_from(&self, shard: &mut Shard, keep: &str, until: impl Fn(&Shard) -> bool) {
+        while self.over_limits() && until(shard) {
+            let Some(oldest) = shard.least_recently_used(keep) else {
+                break;
+            };
+            shard.remove(&oldest);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn since_epoch(&self, now: Instant) -> u64 {
+        now.duration_since(self.epoch).as_nanos() as u64
+    }
+}
+
+#[async_trait]
+impl Cache for MemoryCache {
+    async fn lookup(&self, key: &str) -> Option<CacheHit> {
+        let shard = self.shard(key);
+        let hit = {
+            let guard = shard.read().expect("cache shard lock");
+            let entry = guard.entries.get(key)?;
+            let now = Instant::now();
+            (now < entry.stale_until).then(|| {
+                entry
+                    .last_access
+                    .store(self.since_epoch(now), Ordering::Relaxed);
+                let stale_for = (now >= entry.expires_at).then(|| now - entry.expires_at);
+                (entry.value.clone(), stale_for)
+            })
+        };
+        if let Some((value, stale_for)) = hit {
+            return Some(CacheHit {
+                value: value.decode()?,
+                stale_for,
+            });
+        }
+
+        // Past the grace window: drop it now rather than waiting for the sweeper.
+        let mut guard = shard.write().expect("cache shard lock");
+        if guard
+            .entries
+            .get(key)
+            .is_some_and(|entry| Instant::now() >= entry.stale_until)
+        {
+            guard.remove(key);
+            self.expirations.fetch_add(1, Ordering::Relaxed);
+        }
+        None
+    }
+
+    async fn insert(&self, key: String, method: &str, value: Value, ttl: Duration) {
+        let (value, value_size) = self.encode(value);
+        let size = key.len() + value_size;
+        let limits = self.limits;
+        if size > limits.max_bytes || limits.max_entries == 0 {
+            // Would evict the whole cache and still not fit. An older answer
+            // under the same key is outdated by this one, so it goes too.
+            self.shard(&key)
+                .write()
+                .expect("cache shard lock")
+                .remove(&key);
+            return;
+        }
+
+        let expires_at = Instant::now() + ttl;
+        let entry = CacheEntry {
+            method: method.to_string(),
+            value,
+            expires_at,
+            stale_until: expires_at + self.stale_grace,
+            size,
+            last_access: AtomicU64::new(self.since_epoch(Instant::now())),
+        };
+
+        let index = self.shard_index(&key);
+        {
+            let mut guard = self.shards[index].write().expect("cache shard lock");
+            guard.remove(&key);
+            guard.insert(key.clone(), entry);
+            // Least recently used entries go first, from this shard while it is
+            // over its share.
+            let share = self.shard_limits;
+            self.evict_from(&mut guard, &key, |shard| {
+                shard.entries.len() > share.max_entries || shard.bytes > share.max_bytes
+            });
+        }
+
+        // Then from the others in turn, one shard locked at a time, and from
+        // this shard again if that was not enough.
+        let shards = self.shards.len();
+        for offset in 1..=shards {
+            if !self.over_limits() {
+                break;
+            }
+            let mut guard = self.shards[(index + offset) % shards]
+                .write()
+                .expect("cache shard lock");
+            self.evict_from(&mut guard, &key, |_| true);
+        }
+    }
+
+    async fn purge_expired(&self) -> usize {
+        let now = Instant::now();
+        let mut purged = 0;
+        // One shard at a time, so lookups elsewhere are never blocked.
+        for shard in self.shards.iter() {
+            let mut guard = shard.write().expect("cache shard lock");
+            let expired: Vec<String> = guard
+                .entries
+                .iter()
+                .filter(|(_, entry)| now >= entry.stale_until)
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in &expired {
+                guard.remove(key);
+            }
+            purged += expired.len();
+        }
+        self.expirations.fetch_add(purged as u64, Ordering::Relaxed);
+        purged
+    }
+
+    async fn invalidate_methods(&self, methods: &[&str]) -> usize {
+        let mut removed = 0;
+        for shard in self.shards.iter() {
+            // Shards without such entries are skipped under the read lock.
+            let holds = |shard: &Shard| {
+                methods
+                    .iter()
+                    .any(|method| shard.by_method.contains_key(*method))
+            };
+            if !holds(&shard.read().expect("cache shard lock")) {
+                continue;
+            }
+            let mut guard = shard.write().expect("cache shard lock");
+            let stale: Vec<String> = methods
+                .iter()
+                .filter_map(|method| guard.by_method.get(*method))
+                .flatten()
+                .cloned()
+                .collect();
+            for key in &stale {
+                guard.remove(key);
+            }
+            removed += stale.len();
+        }
+        self.invalidations
+            .fetch_add(removed as u64, Ordering::Relaxed);
+        removed
+    }
+
+    async fn remove(&self, key: &str) -> bool {
+        let mut guard = self.shard(key).write().expect("cache shard lock");
+        let removed = guard.entries.contains_key(key);
+        if removed {
+            guard.remove(key);
+            self.invalidations.fetch_add(1, Ordering::Relaxed);
+        }
+        removed
+    }
+
+    async fn clear(&self) -> usize {
+        let mut removed = 0;
+        for shard in self.shards.iter() {
+            let mut guard = shard.write().expect("cache shard lock");
+            removed += guard.entries.len();
+            guard.clear();
+        }
+        self.invalidations
+            .fetch_add(removed as u64, Ordering::Relaxed);
+        removed
+    }
+
+    async fn stats(&self) -> CacheStats {
+        let mut stats = CacheStats {
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+            ..CacheStats::default()
+        };
+        let mut compression = CompressionStats::default();
+        for shard in self.shards.iter() {
+            let guard = shard.read().expect("cache shard lock");
+            stats.entries += guard.entries.len();
+            stats.bytes += guard.bytes;
+            compression.entries += guard.compressed;
+            compression.raw_bytes += guard.compressed_raw_bytes;
+            compression.stored_bytes += guard.compressed_bytes;
+        }
+        if self.compress_min_bytes.is_some() {
+            compression.ratio = if compression.stored_bytes == 0 {
+                1.0
+            } else {
+                compression.raw_bytes as f64 / compression.stored_bytes as f64
+            };
+            stats.compression = Some(compression);
+        }
+        stats
+    }
+
+    async fn method_stats(&self) -> BTreeMap<String, MethodStats> {
+        let mut methods: BTreeMap<String, MethodStats> = BTreeMap::new();
+        for shard in self.shards.iter() {
+            let guard = shard.read().expect("cache shard lock");
+            for entry in guard.entries.values() {
+                let stats = methods.entry(entry.method.clone()).or_default();
+                stats.entries += 1;
+                stats.bytes += entry.size;
+            }
+        }
+        methods
+    }
+
+    async fn export(&self) -> Vec<ExportedEntry> {
+        let now = Instant::now();
+        let mut fresh = Vec::new();
+        for shard in self.shards.iter() {
+            let guard = shard.read().expect("cache shard lock");
+            fresh.extend(
+                guard
+                    .entries
+                    .iter()
+                    .filter(|(_, entry)| now < entry.expires_at)
+                    .map(|(key, entry)| {
+                        (
+                            key.clone(),
+                            entry.method.clone(),
+                            entry.value.clone(),
+                            entry.expires_at - now,
+                        )
+                    }),
+            );
+        }
+        // Inflated with no shard locked.
+        fresh
+            .into_iter()
+            .filter_map(|(key, method, value, ttl)| {
+                Some(ExportedEntry {
+                    key,
+                    method,
+                    value: value.decode()?,
+                    ttl,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Deflated `raw`, unless that is no smaller: already dense data is not worth
+/// inflating on every hit.
+fn deflate(raw: &[u8]) -> Option<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+    encoder.write_all(raw).ok()?;
+    encoder
+        .finish()
+        .ok()
+        .filter(|compressed| compressed.len() < raw.len())
+}
+
+impl Shard {
+    fn new(totals: Arc<Totals>) -> Self {
+        Self {
+            totals,
+            entries: HashMap::new(),
+            by_method: HashMap::new(),
+            bytes: 0,
+            compressed: 0,
+            compressed_raw_bytes: 0,
+            compressed_bytes: 0,
+        }
+    }
+
+    fn least_recently_used(&self, keep: &str) -> Option<String> {
+        // Recency lives in atomics, so finding the oldest entry is a scan of one shard.
+        self.entries
+            .iter()
+            .filter(|(key, _)| key.as_str() != keep)
+            .min_by_key(|(_, entry)| entry.last_access.load(Ordering::Relaxed))
+            .map(|(key, _)| key.clone())
+    }
+
+    fn insert(&mut self, key: String, entry: CacheEntry) {
+        self.totals.entries.fetch_add(1, Ordering::Relaxed);
+        self.totals.bytes.fetch_add(entry.size, Ordering::Relaxed);
+        self.bytes += entry.size;
+        if let StoredValue::Compressed { bytes, raw_len } = &entry.value {
+            self.compressed += 1;
+            self.compressed_raw_bytes += raw_len;
+            self.compressed_bytes += bytes.len();
+        }
+        self.by_method
+            .entry(entry.method.clone())
+            .or_default()
+            .insert(key.clone());
+        self.entries.insert(key, entry);
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.totals.entries.fetch_sub(1, Ordering::Relaxed);
+            self.totals.bytes.fetch_sub(entry.size, Ordering::Relaxed);
+            self.bytes -= entry.size;
+            if let StoredValue::Compressed { bytes, raw_len } = &entry.value {
+                self.compressed -= 1;
+                self.compressed_raw_bytes -= raw_len;
+                self.compressed_bytes -= bytes.len();
+            }
+            if let Some(keys) = self.by_method.get_mut(&entry.method) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.by_method.remove(&entry.method);
+                }
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.totals
+            .entries
+            .fetch_sub(self.entries.len(), Ordering::Relaxed);
+        self.totals.bytes.fetch_sub(self.bytes, Ordering::Relaxed);
+        *self = Self::new(self.totals.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    // A single shard makes eviction order exact.
+    fn cache(max_entries: usize, max_bytes: usize) -> MemoryCache {
+        MemoryCache::with_shards(
+            CacheLimits {
+                max_entries,
+                max_bytes,
+            },
+            1,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_evicts_least_recently_used_entry() {
+        let cache = cache(2, usize::MAX);
+        cache
+            .insert("a".to_string(), "getSlot", json!(1), TTL)
+            .await;
+        cache
+            .insert("b".to_string(), "getSlot", json!(2), TTL)
+            .await;
+
+        // Reading `a` makes `b` the eviction candidate.
+        assert_eq!(cache.get("a").await, Some(json!(1)));
+        cache
+            .insert("c".to_string(), "getSlot", json!(3), TTL)
+            .await;
+
+        assert_eq!(cache.get("b").await, None);
+        assert_eq!(cache.get("a").await, Some(json!(1)));
+        assert_eq!(cache.get("c").await, Some(json!(3)));
+        assert_eq!(cache.stats().await.evictions, 1);
+    }
+
+    #[tokio::test]
+    async fn test_byte_limit_bounds_cache_size() {
+        // Each entry is a 1-byte key plus a 10-byte string value.
+        let cache = cache(100, 25);
+        for key in ["a", "b", "c"] {
+            cache
+                .insert(key.to_string(), "getSlot", json!("12345678"), TTL)
+                .await;
+        }
+
+        let stats = cache.stats().await;
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.bytes, 22);
+        assert_eq!(stats.evictions, 1);
+
+        // An entry larger than the whole budget is not cached at all.
+        cache
+            .insert("big".to_string(), "getSlot", json!("x".repeat(64)), TTL)
+            .await;
+        assert_eq!(cache.get("big").await, None);
+        assert_eq!(cache.stats().await.entries, 2);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_answer_drops_the_older_one() {
+        let cache = cache(100, 25);
+        cache
+            .insert("b".to_string(), "getSlot", json!("old"), TTL)
+            .await;
+
+        cache
+            .insert("b".to_string(), "getSlot", json!("x".repeat(64)), TTL)
+            .await;
+        assert_eq!(cache.get("b").await, None);
+        let stats = cache.stats().await;
+        assert_eq!(stats.entries, 0);
+        assert_eq!(stats.bytes, 0);
+    }
+
+    #[tokio::test]
+    async fn test_limits_apply_to_the_whole_cache() {
+        let cache = MemoryCache::new(CacheLimits {
+            max_entries: 10,
+            max_bytes: 16 * 1_024,
+        });
+        // Larger than a shard's share of the byte budget, but within the total.
+        let block = json!("x".repeat(4_096));
+        cache
+            .insert("block".to_string(), "getBlock", block.clone(), TTL)
+            .await;
+        assert_eq!(cache.get("block").await, Some(block));
+
+        for key in 0..20 {
+            cache
+                .insert(key.to_string(), "getSlot", json!(key), TTL)
+                .await;
+        }
+        let stats = cache.stats().await;
+        assert_eq!(stats.entries, 10);
+        assert_eq!(stats.evictions, 11);
+    }
+
+    #[tokio::test]
+    async fn test_purge_expired_removes_stale_entries() {
+        let cache = MemoryCache::new(CacheLimits {
+            max_entries: 64,
+            max_bytes: usize::MAX,
+        });
+        cache
+            .insert("a".to_string(), "getSlot", json!(1), Duration::ZERO)
+            .await;
+        cache
+            .insert("b".to_string(), "getSlot", json!(2), TTL)
+            .await;
+        cache
+            .insert("c".to_string(), "getSlot", json!(3), Duration::ZERO)
+            .await;
+
+        assert_eq!(cache.purge_expired().await, 2);
+        let stats = cache.stats().await;
+        assert_eq!(stats.entries, 1);
+        assert_eq!(stats.expirations, 2);
+        assert_eq!(cache.get("b").await, Some(json!(2)));
+    }
+
+    // Wall-clock timing is unreliable on shared runners; run it on an idle machine with
+    // `cargo test --release test_hit_throughput -- --ignored`.
+    #[test]
+    #[ignore]
+    fn test_hit_throughput_scales_with_concurrency() {
+        const KEYS: usize = 256;
+        const LOOKUPS: usize = 50_000;
+
+        let cache = Arc::new(MemoryCache::new(CacheLimits {
+            max_entries: KEYS * 2,
+            max_bytes: usize::MAX,
+        }));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            for key in 0..KEYS {
+                cache
+                    .insert(
+                        key.to_string(),
+                        "getSlot",
+                        json!({"slot": key, "value": "x"}),
+                        TTL,
+                    )
+                    .await;
+            }
+        });
+
+        // Hits per second with `threads` readers doing LOOKUPS each.
+        let hit_rate = |threads: usize| {
+            let started = Instant::now();
+            std::thread::scope(|scope| {
+                for thread in 0..threads {
+                    let cache = cache.clone();
+                    scope.spawn(move || {
+                        let runtime = tokio::runtime::Builder::new_current_thread()
+                            .build()
+                            .unwrap();
+                        runtime.block_on(async {
+                            for lookup in 0..LOOKUPS {
+                                let key = ((lookup * 31 + thread) % KEYS).to_string();
+                                assert!(cache.get(&key).await.is_some());
+                            }
+                        });
+                    });
+                }
+            });
+            (threads * LOOKUPS) as f64 / started.elapsed().as_secs_f64()
+        };
+
+        let threads = std::thread::available_parallelism()
+            .map_or(1, |count| count.get())
+            .min(4);
+        let single = hit_rate(1);
+        let concurrent = hit_rate(threads);
+        // With a global lock the rate would stay flat; allow generous headroom for noisy CI.
+        let expected = 1.0 + (threads - 1) as f64 * 0.4;
+        assert!(
+            concurrent >= single * expected * 0.8,
+            "hit throughput did not scale: {:.0}/s vs {:.0}/s",
+            concurrent,
+            single
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_methods_keeps_other_entries() {
+        let cache = cache(10, usize::MAX);
+        cache
+            .insert("a".to_string(), "getSlot", json!(1), TTL)
+            .await;
+        cache
+            .insert("b".to_string(), "getBalance", json!(2), TTL)
+            .await;
+
+        assert_eq!(cache.invalidate_methods(&["getSlot"]).await, 1);
+        assert_eq!(cache.get("a").await, None);
+        assert_eq!(cache.get("b").await, Some(json!(2)));
+        assert_eq!(cache.stats().await.invalidations, 1);
+
+        // Replaced and removed entries leave the method index too.
+        cache
+            .insert("b".to_string(), "getSlot", json!(3), TTL)
+            .await;
+        assert_eq!(cache.invalidate_methods(&["getBalance"]).await, 0);
+        assert!(cache.remove("b").await);
+        assert_eq!(cache.invalidate_methods(&["getSlot"]).await, 0);
+        assert!(cache.shards[0].read().unwrap().by_method.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_large_entries_are_stored_compressed() {
+        let cache = cache(10, usize::MAX).with_compression(Some(1_024));
+        // Like an account's base64 data: large and repetitive.
+        let account = json!({"data": ["AAAA".repeat(4_096), "base64"], "lamports": 1});
+        let raw_len = serde_json::to_vec(&account).unwrap().len();
+        cache
+            .insert(
+                "account".to_string(),
+                "getAccountInfo",
+                account.clone(),
+                TTL,
+            )
+            .await;
+        cache
+            .insert("slot".to_string(), "getSlot", json!(1), TTL)
+            .await;
+
+        assert_eq!(cache.get("account").await, Some(account.clone()));
+        assert_eq!(cache.get("slot").await, Some(json!(1)));
+        let stats = cache.stats().await;
+        let compression = stats.compression.unwrap();
+        assert_eq!(compression.entries, 1);
+        assert_eq!(compression.raw_bytes, raw_len);
+        assert!(compression.ratio > 10.0);
+        assert_eq!(
+            stats.bytes,
+            "account".len() + compression.stored_bytes + "slot".len() + 1
+        );
+        assert_eq!(cache.export().await.len(), 2);
+
+        assert!(cache.remove("account").await);
+        assert_eq!(cache.stats().await.compression.unwrap().stored_bytes, 0);
+    }
+
+    #[tokio::test]
+    async fn test_expired_entry_is_stale_until_grace_runs_out() {
+        let cache = cache(10, usize::MAX).with_stale_grace(Duration::from_millis(100));
+        cache
+            .insert("a".to_string(), "getSlot", json!(1), Duration::ZERO)
+            .await;
+
+        let hit = cache.lookup("a").await.unwrap();
+        assert_eq!(hit.value, json!(1));
+        assert!(hit.stale_for.is_some());
+        assert_eq!(cache.get("a").await, None);
+        assert_eq!(cache.purge_expired().await, 0);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(cache.lookup("a").await, None);
+        assert_eq!(cache.stats().await.entries, 0);
+    }
+}
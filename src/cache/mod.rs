@@ -0,0 +1,204 @@
+//! Response cache keyed by normalized request hash, behind a backend trait so
+//! deployments can choose where entries live.
+
+mod disk;
+mod memory;
+mod redis_cache;
+
+pub use disk::{DiskCache, DiskConfig};
+pub use memory::MemoryCache;
+pub use redis_cache::{RedisCache, RedisConfig};
+
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[async_trait]
+pub trait Cache: Send + Sync {
+    /// Response stored under `key`, while fresh or within the stale grace window.
+    async fn lookup(&self, key: &str) -> Option<CacheHit>;
+
+    /// Fresh response stored under `key`, if any; the proxy goes through
+    /// `lookup` so it can serve stale entries.
+    #[cfg(test)]
+    async fn get(&self, key: &str) -> Option<Value> {
+        self.lookup(key)
+            .await
+            .filter(|hit| hit.stale_for.is_none())
+            .map(|hit| hit.value)
+    }
+
+    /// Stores the response to a `method` call until `ttl` has passed.
+    async fn insert(&self, key: String, method: &str, value: Value, ttl: Duration);
+
+    /// Drops the entry stored under `key`; returns whether there was one.
+    async fn remove(&self, key: &str) -> bool;
+
+    /// Drops every entry cached for one of `methods`; returns how many were removed.
+    async fn invalidate_methods(&self, methods: &[&str]) -> usize;
+
+    /// Drops every entry; returns how many were removed.
+    async fn clear(&self) -> usize;
+
+    /// Drops every expired entry; returns how many were removed.
+    async fn purge_expired(&self) -> usize;
+
+    async fn stats(&self) -> CacheStats;
+
+    /// Entries and bytes per RPC method, covering the same entries as `stats`.
+    async fn method_stats(&self) -> BTreeMap<String, MethodStats>;
+
+    /// Every fresh entry with its remaining TTL, for carrying the cache over to
+    /// another instance.
+    async fn export(&self) -> Vec<ExportedEntry>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CacheHit {
+    pub value: Value,
+    /// How long the entry is past its TTL; `None` while it is fresh.
+    pub stale_for: Option<Duration>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportedEntry {
+    pub key: String,
+    pub method: String,
+    pub value: Value,
+    /// Time left until the entry expires.
+    pub ttl: Duration,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CacheLimits {
+    pub max_entries: usize,
+    /// Approximate budget: serialized response size plus key length.
+    pub max_bytes: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: usize,
+    /// Entries dropped to stay within the limits.
+    pub evictions: u64,
+    /// Entries dropped because their TTL ran out.
+    pub expirations: u64,
+    /// Entries dropped early because the chain moved on or an operator purged them.
+    pub invalidations: u64,
+    /// Entries held compressed in memory; `None` when compression is off.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<CompressionStats>,
+    /// Entries persisted by the disk backend.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk: Option<DiskStats>,
+    /// Store state of the Redis backend; the counters above cover its fallback.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redis: Option<RedisStats>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct MethodStats {
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct CompressionStats {
+    pub entries: usize,
+    /// Serialized size of the compressed entries.
+    pub raw_bytes: usize,
+    /// What they take compressed; counted in `bytes` instead of `raw_bytes`.
+    pub stored_bytes: usize,
+    /// `raw_bytes` divided by `stored_bytes`.
+    pub ratio: f64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct DiskStats {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct RedisStats {
+    pub connected: bool,
+    /// Operations served by the in-memory fallback because the store was unreachable.
+    pub fallbacks: u64,
+}
+
+/// Where cached responses are kept.
+#[derive(Clone, Debug)]
+pub enum CacheBackend {
+    Memory,
+    /// In memory, with long-lived entries also written to disk to survive restarts.
+    Disk(DiskConfig),
+    /// Shared between replicas in a Redis-compatible store.
+    Redis(RedisConfig),
+}
+
+impl CacheBackend {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CacheBackend::Memory => "memory",
+            CacheBackend::Disk(_) => "disk",
+            CacheBackend::Redis(_) => "redis",
+        }
+    }
+}
+
+/// Builds the configured backend, keeping expired entries for `stale_grace`
+/// and compressing in-memory entries of at least `compress_min_bytes`; panics
+/// if the disk cache cannot be opened or the Redis URL is invalid.
+pub fn open_cache(
+    limits: CacheLimits,
+    stale_grace: Duration,
+    compress_min_bytes: Option<usize>,
+    backend: &CacheBackend,
+) -> Arc<dyn Cache> {
+    match backend {
+        CacheBackend::Memory => Arc::new(
+            MemoryCache::new(limits)
+                .with_stale_grace(stale_grace)
+                .with_compression(compress_min_bytes),
+        ),
+        CacheBackend::Disk(config) => match DiskCache::open(limits, stale_grace, config.clone()) {
+            Ok(cache) => Arc::new(cache.with_compression(compress_min_bytes)),
+            Err(err) => panic!(
+                "failed to open disk cache at {}: {}",
+                config.dir.display(),
+                err
+            ),
+        },
+        CacheBackend::Redis(config) => {
+            match RedisCache::open(limits, stale_grace, config.clone()) {
+                Ok(cache) => Arc::new(cache.with_compression(compress_min_bytes)),
+                // The URL can carry a password, so only the error is reported.
+                Err(err) => panic!("invalid REDIS_URL: {}", err),
+            }
+        }
+    }
+}
+
+/// Periodically purges expired entries so keys that are never read again do
+/// not hold memory until they are evicted.
+pub async fn run_sweeper(cache: Arc<dyn Cache>, every: Duration) {
+    let mut ticker = tokio::time::interval(every);
+    loop {
+        ticker.tick().await;
+        let purged = cache.purge_expired().await;
+        if purged > 0 {
+            tracing::debug!(purged, "swept expired cache entries");
+        }
+    }
+}
+
+/// Wall-clock milliseconds since the Unix epoch, for expiry that outlives the process.
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
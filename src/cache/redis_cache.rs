@@ -0,0 +1,445 @@
+//! Shared cache in a Redis-compatible store, so gateway replicas answer from
+//! one set of entries.
+//!
+//! Entries live under `<prefix>:<request hash>` with native expiry (`PX`, which
+//! includes the stale grace window), and a sorted set per method
+//! (`<prefix>:keys:<name>`) lists its keys by expiry for invalidation; members
+//! that have expired are pruned on every insert, so the set only tracks live
+//! entries.
+//! While the store is unreachable every operation falls back to a local
+//! `MemoryCache`, and reconnection is retried after a delay.
+
+use super::{
+    now_ms, Cache, CacheHit, CacheLimits, CacheStats, ExportedEntry, MemoryCache, MethodStats,
+    RedisStats,
+};
+use async_trait::async_trait;
+use redis::aio::MultiplexedConnection;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::time::timeout;
+
+/// How long commands and connection attempts may take before falling back.
+const COMMAND_TIMEOUT: Duration = Duration::from_millis(250);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug)]
+pub struct RedisConfig {
+    pub url: String,
+    /// Namespace for every key, so several deployments can share one store;
+    /// keys are `<prefix>:<name>`, so a trailing `:` is optional.
+    pub key_prefix: String,
+}
+
+pub struct RedisCache {
+    client: redis::Client,
+    key_prefix: String,
+    stale_grace: Duration,
+    state: Mutex<Connection>,
+    fallback: MemoryCache,
+    fallbacks: AtomicU64,
+}
+
+enum Connection {
+    Connected(MultiplexedConnection),
+    /// Unreachable; no new attempt before the given instant.
+    Down(Instant),
+}
+
+/// Stored form of one entry.
+#[derive(Serialize, Deserialize)]
+struct StoredEntry {
+    method: String,
+    /// End of the TTL in milliseconds since the Unix epoch.
+    fresh_until: u64,
+    value: Value,
+}
+
+impl RedisCache {
+    /// Validates the URL without connecting; the first operation connects.
+    pub fn open(
+        limits: CacheLimits,
+        stale_grace: Duration,
+        config: RedisConfig,
+    ) -> redis::RedisResult<Self> {
+        Ok(Self {
+            client: redis::Client::open(config.url)?,
+            key_prefix: config.key_prefix.trim_end_matches(':').to_string(),
+            stale_grace,
+            state: Mutex::new(Connection::Down(Instant::now())),
+            fallback: MemoryCache::new(limits).with_stale_grace(stale_grace),
+            fallbacks: AtomicU64::new(0),
+        })
+    }
+
+    /// Compresses fallback entries of at least `min_bytes`.
+    pub fn with_compression(mut self, min_bytes: Option<usize>) -> Self {
+        self.fallback = self.fallback.with_compression(min_bytes);
+        self
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}:{}", self.key_prefix, key)
+    }
+
+    fn method_key(&self, method: &str) -> String {
+        format!("{}:keys:{}", self.key_prefix, method)
+    }
+
+    /// Matches every key of this namespace and none of a namespace that merely
+    /// starts with the same name.
+    fn scan_pattern(&self) -> String {
+        format!("{}:*", self.key_prefix)
+    }
+
+    /// Current connection, reconnecting once the retry delay has passed.
+    async fn connection(&self) -> Option<MultiplexedConnection> {
+        {
+            let state = self.state.lock().expect("redis state lock");
+            match &*state {
+                Connection::Connected(connection) => return Some(connection.clone()),
+                Connection::Down(retry_at) if Instant::now() < *retry_at => return None,
+                Connection::Down(_) => {}
+            }
+        }
+
+        let connected = timeout(
+            COMMAND_TIMEOUT,
+            self.client.get_multiplexed_async_connection(),
+        )
+        .await;
+        match connected {
+            Ok(Ok(connection)) => {
+                tracing::info!("connected to redis cache");
+                *self.state.lock().expect("redis state lock") =
+                    Connection::Connected(connection.clone());
+                Some(connection)
+            }
+            Ok(Err(err)) => {
+                self.mark_down(&err.to_string());
+                None
+            }
+            Err(_) => {
+                self.mark_down("connection timed out");
+                None
+            }
+        }
+    }
+
+    fn mark_down(&self, reason: &str) {
+        let mut state = self.state.lock().expect("redis state lock");
+        if matches!(*state, Connection::Connected(_)) {
+            tracing::warn!(reason = %reason, "redis cache unreachable, using in-memory fallback");
+        }
+        *state = Connection::Down(Instant::now() + RECONNECT_DELAY);
+    }
+
+    /// Runs one store operation, or returns `None` after marking the store down.
+    async fn run<T, F>(&self, operation: impl FnOnce(MultiplexedConnection) -> F) -> Option<T>
+    where
+        F: std::future::Future<Output = redis::RedisResult<T>>,
+    {
+        let Some(connection) = self.connection().await else {
+            self.fallbacks.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        match timeout(COMMAND_TIMEOUT, operation(connection)).await {
+            Ok(Ok(result)) => Some(result),
+            Ok(Err(err)) => {
+                self.mark_down(&err.to_string());
+                self.fallbacks.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+            Err(_) => {
+                self.mark_down("command timed out");
+                self.fallbacks.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Cache for RedisCache {
+    async fn lookup(&self, key: &str) -> Option<CacheHit> {
+        let stored_key = self.key(key);
+        let stored =
+            self.run(|mut connection| async move {
+                connection.get::<_, Option<Vec<u8>>>(stored_key).await
+            })
+            .await;
+        let Some(stored) = stored else {
+            return self.fallback.lookup(key).await;
+        };
+
+        let entry: StoredEntry = serde_json::from_slice(&stored?).ok()?;
+        let now = now_ms();
+        Some(CacheHit {
+            value: entry.value,
+            stale_for: (now >= entry.fresh_until)
+                .then(|| Duration::from_millis(now - entry.fresh_until)),
+        })
+    }
+
+    async fn insert(&self, key: String, method: &str, value: Value, ttl: Duration) {
+        let entry = StoredEntry {
+            method: method.to_string(),
+            fresh_until: now_ms() + ttl.as_millis() as u64,
+            value,
+        };
+        let Ok(bytes) = serde_json::to_vec(&entry) else {
+            return;
+        };
+        let stored_key = self.key(&key);
+        let method_key = self.method_key(method);
+        let ttl_ms = (ttl + self.stale_grace).as_millis().max(1) as u64;
+        let now = now_ms();
+        let stored = self
+            .run(|mut connection| async move {
+                redis::pipe()
+                    .pset_ex(&stored_key, bytes, ttl_ms)
+                    .ignore()
+                    .zadd(&method_key, &stored_key, now + ttl_ms)
+                    .ignore()
+                    .zrembyscore(&method_key, 0, now)
+                    .ignore()
+                    .query_async::<()>(&mut connection)
+                    .await
+            })
+            .await;
+        if stored.is_none() {
+            self.fallback
+                .insert(key, &entry.method, entry.value, ttl)
+                .await;
+        }
+    }
+
+    async fn remove(&self, key: &str) -> bool {
+        let stored_key = self.key(key);
+        let removed = self
+            .run(|mut connection| async move { connection.del::<_, usize>(stored_key).await })
+            .await;
+        // Entries cached during an outage may still be in the fallback.
+        let in_fallback = self.fallback.remove(key).await;
+        removed.is_some_and(|count| count > 0) || in_fallback
+    }
+
+    async fn invalidate_methods(&self, methods: &[&str]) -> usize {
+        let method_keys: Vec<String> = methods
+            .iter()
+            .map(|method| self.method_key(method))
+            .collect();
+        let removed = self
+            .run(|mut connection| async move {
+                let mut removed = 0;
+                for method_key in method_keys {
+                    // Members may already have expired; DEL skips missing keys.
+                    let keys: Vec<String> = connection.zrange(&method_key, 0, -1).await?;
+                    if !keys.is_empty() {
+                        removed += connection.del::<_, usize>(&keys).await?;
+                    }
+                    connection.del::<_, ()>(&method_key).await?;
+                }
+                Ok(removed)
+            })
+            .await
+            .unwrap_or(0);
+        removed + self.fallback.invalidate_methods(methods).await
+    }
+
+    async fn clear(&self) -> usize {
+        let pattern = self.scan_pattern();
+        let method_keys = self.method_key("");
+        let removed = self
+            .run(|mut connection| async move {
+                let keys: Vec<String> = {
+                    let mut keys = connection.scan_match::<_, String>(&pattern).await?;
+                    let mut collected = Vec::new();
+                    while let Some(key) = keys.next_item().await {
+                        collected.push(key);
+                    }
+                    collected
+                };
+                let mut removed = 0;
+                for chunk in keys.chunks(500) {
+                    connection.del::<_, ()>(chunk).await?;
+                    removed += chunk
+                        .iter()
+                        .filter(|key| !key.starts_with(&method_keys))
+                        .count();
+                }
+                Ok(removed)
+            })
+            .await
+            .unwrap_or(0);
+        removed + self.fallback.clear().await
+    }
+
+    async fn purge_expired(&self) -> usize {
+        // The store expires its own entries; only the fallback needs sweeping.
+        self.fallback.purge_expired().await
+    }
+
+    async fn stats(&self) -> CacheStats {
+        let mut stats = self.fallback.stats().await;
+        let connected = matches!(
+            *self.state.lock().expect("redis state lock"),
+            Connection::Connected(_)
+        );
+        stats.redis = Some(RedisStats {
+            connected,
+            fallbacks: self.fallbacks.load(Ordering::Relaxed),
+        });
+        stats
+    }
+
+    async fn method_stats(&self) -> BTreeMap<String, MethodStats> {
+        // Like `stats`, the counts describe the fallback.
+        self.fallback.method_stats().await
+    }
+
+    async fn export(&self) -> Vec<ExportedEntry> {
+        // Entries in the store are already shared with every instance using it.
+        self.fallback.export().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const LIMITS: CacheLimits = CacheLimits {
+        max_entries: 100,
+        max_bytes: usize::MAX,
+    };
+
+    fn open(url: String) -> RedisCache {
+        // A prefix per run keeps parallel runs against one server apart.
+        let key_prefix = format!("qn-gateway-test:{}", std::process::id());
+        RedisCache::open(LIMITS, Duration::ZERO, RedisConfig { url, key_prefix }).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_store_falls_back_to_memory() {
+        let cache = open("redis://127.0.0.1:1".to_string());
+        cache
+            .insert(
+                "a".to_string(),
+                "getBlock",
+                json!(1),
+                Duration::from_secs(60),
+            )
+            .await;
+
+        assert_eq!(cache.get("a").await, Some(json!(1)));
+        let stats = cache.stats().await;
+        assert_eq!(stats.entries, 1);
+        let redis = stats.redis.unwrap();
+        assert!(!redis.connected);
+        assert!(redis.fallbacks >= 2);
+
+        assert!(cache.remove("a").await);
+        assert_eq!(cache.get("a").await, None);
+    }
+
+    #[test]
+    fn test_keys_stay_within_their_namespace() {
+        let config = |key_prefix: &str| RedisConfig {
+            url: "redis://127.0.0.1:1".to_string(),
+            key_prefix: key_prefix.to_string(),
+        };
+        let gw = RedisCache::open(LIMITS, Duration::ZERO, config("gw")).unwrap();
+        let with_colon = RedisCache::open(LIMITS, Duration::ZERO, config("gw:")).unwrap();
+        let gw2 = RedisCache::open(LIMITS, Duration::ZERO, config("gw2")).unwrap();
+
+        assert_eq!(gw.key("abc"), "gw:abc");
+        assert_eq!(with_colon.key("abc"), "gw:abc");
+        assert_eq!(gw.method_key("getSlot"), "gw:keys:getSlot");
+        assert_eq!(gw.scan_pattern(), "gw:*");
+        // `gw:*` never reaches into the `gw2` namespace.
+        assert_eq!(gw2.key("abc"), "gw2:abc");
+    }
+
+    /// Needs a Redis-compatible server, e.g. `REDIS_URL=redis://127.0.0.1:6379`.
+    #[tokio::test]
+    #[ignore]
+    async fn test_entries_are_shared_through_the_store() {
+        let url = std::env::var("REDIS_URL").expect("REDIS_URL");
+        let writer = open(url.clone());
+        let reader = open(url.clone());
+
+        writer
+            .insert(
+                "slot".to_string(),
+                "getSlot",
+                json!(5),
+                Duration::from_secs(60),
+            )
+            .await;
+        writer
+            .insert(
+                "block".to_string(),
+                "getBlock",
+                json!({"slot": 5}),
+                Duration::from_secs(60),
+            )
+            .await;
+        writer
+            .insert(
+                "short".to_string(),
+                "getBlock",
+                json!(1),
+                Duration::from_millis(50),
+            )
+            .await;
+
+        assert_eq!(reader.get("slot").await, Some(json!(5)));
+        assert_eq!(reader.get("block").await, Some(json!({"slot": 5})));
+        assert!(reader.stats().await.redis.unwrap().connected);
+
+        // Native expiry, method invalidation and removal are visible to every replica.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(reader.get("short").await, None);
+
+        // The next insert prunes the expired key from the method's index.
+        writer
+            .insert(
+                "block2".to_string(),
+                "getBlock",
+                json!({"slot": 6}),
+                Duration::from_secs(60),
+            )
+            .await;
+        let mut connection = redis::Client::open(url)
+            .unwrap()
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+        let indexed: usize = connection
+            .zcard(writer.method_key("getBlock"))
+            .await
+            .unwrap();
+        assert_eq!(indexed, 2);
+        assert_eq!(reader.invalidate_methods(&["getSlot"]).await, 1);
+        assert_eq!(writer.get("slot").await, None);
+        assert!(reader.remove("block").await);
+        assert_eq!(writer.get("block").await, None);
+
+        writer
+            .insert(
+                "slot".to_string(),
+                "getSlot",
+                json!(6),
+                Duration::from_secs(60),
+            )
+            .await;
+        assert_eq!(reader.clear().await, 2);
+        assert_eq!(writer.get("slot").await, None);
+    }
+}
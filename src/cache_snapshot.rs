@@ -0,0 +1,209 @@
+//! Cache snapshots: fresh entries with their remaining TTL, written by one
+//! instance and read by the next so a deploy does not start with a cold cache.
+//!
+//! Entries are keyed by request hash, so a snapshot is only accepted when the
+//! hashes were computed the same way: same normalization version, privacy mode
+//! and cluster commitment.
+
+use crate::account_watcher::is_watched_method;
+use crate::cache::now_ms;
+use crate::normalize::NORMALIZATION_VERSION;
+use crate::server::AppState;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub normalization_version: u32,
+    pub privacy_mode: String,
+    pub cluster_commitment: String,
+    /// When the snapshot was taken, in milliseconds since the Unix epoch; TTLs
+    /// count from here.
+    pub created_at_ms: u64,
+    pub entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    pub request_hash: String,
+    pub method: String,
+    pub ttl_ms: u64,
+    pub value: Value,
+}
+
+/// Snapshot of every fresh entry in the cache.
+pub async fn export_snapshot(state: &AppState) -> Snapshot {
+    let entries = state
+        .cache
+        .export()
+        .await
+        .into_iter()
+        .map(|entry| SnapshotEntry {
+            request_hash: entry.key,
+            method: entry.method,
+            ttl_ms: entry.ttl.as_millis() as u64,
+            value: entry.value,
+        })
+        .collect();
+    Snapshot {
+        normalization_version: NORMALIZATION_VERSION,
+        privacy_mode: state.config.privacy_mode.to_string(),
+        cluster_commitment: state.config.default_commitment.to_string(),
+        created_at_ms: now_ms(),
+        entries,
+    }
+}
+
+/// Inserts the entries of `snapshot` that are still fresh; returns how many.
+/// Fails without inserting anything when its hashes are incompatible.
+pub async fn import_snapshot(state: &AppState, snapshot: Snapshot) -> Result<usize, String> {
+    check_compatible(state, &snapshot)?;
+
+    let elapsed = now_ms().saturating_sub(snapshot.created_at_ms);
+    let mut imported = 0;
+    for entry in snapshot.entries {
+        // Without the request there is no account to subscribe to.
+        if state.account_watcher.is_some() && is_watched_method(&entry.method) {
+            continue;
+        }
+        // Never longer than this instance would cache the method or the answer.
+        let remaining = Duration::from_millis(entry.ttl_ms.saturating_sub(elapsed))
+            .min(state.config.cache_ttls.for_method(&entry.method));
+        let Some(remaining) = state
+            .config
+            .negative_cache
+            .response_ttl(&entry.method, &entry.value, remaining)
+            .filter(|remaining| !remaining.is_zero())
+        else {
+            continue;
+        };
+        state
+            .cache
+            .insert(entry.request_hash, &entry.method, entry.value, remaining)
+            .await;
+        imported += 1;
+    }
+    Ok(imported)
+}
+
+/// Imports the snapshot at `path` at startup; a missing, unreadable or
+/// incompatible snapshot only costs the warm start.
+pub async fn import_snapshot_file(state: &AppState, path: &Path) {
+    let snapshot = tokio::fs::read(path)
+        .await
+        .map_err(|err| err.to_string())
+        .and_then(|bytes| {
+            serde_json::from_slice::<Snapshot>(&bytes).map_err(|err| err.to_string())
+        });
+    let result = match snapshot {
+        Ok(snapshot) => import_snapshot(state, snapshot).await,
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(imported) => {
+            tracing::info!(imported, path = %path.display(), "imported cache snapshot")
+        }
+        Err(err) => {
+            tracing::warn!(error = %err, path = %path.display(), "cache snapshot not imported")
+        }
+    }
+}
+
+fn check_compatible(state: &AppState, snapshot: &Snapshot) -> Result<(), String> {
+    if snapshot.normalization_version != NORMALIZATION_VERSION {
+        return Err(format!(
+            "snapshot normalization version {} does not match {}",
+            snapshot.normalization_version, NORMALIZATION_VERSION
+        ));
+    }
+    let privacy_mode = state.config.privacy_mode.to_string();
+    if snapshot.privacy_mode != privacy_mode {
+        return Err(format!(
+            "snapshot privacy mode {} does not match {}",
+            snapshot.privacy_mode, privacy_mode
+        ));
+    }
+    let commitment = state.config.default_commitment.to_string();
+    if snapshot.cluster_commitment != commitment {
+        return Err(format!(
+            "snapshot cluster commitment {} does not match {}",
+            snapshot.cluster_commitment, commitment
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::privacy_mode::PrivacyMode;
+    use crate::proxy::tests::test_state;
+    use serde_json::json;
+
+    fn state() -> AppState {
+        test_state("http://127.0.0.1:1".to_string(), PrivacyMode::Strict)
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_carries_fresh_entries_over() {
+        let old = state();
+        let ttl = Duration::from_secs(3_600);
+        old.cache
+            .insert("block".to_string(), "getBlock", json!({"slot": 1}), ttl)
+            .await;
+        old.cache
+            .insert("gone".to_string(), "getSlot", json!(1), Duration::ZERO)
+            .await;
+        let error = json!({"error": {"code": -32602, "message": "Invalid param"}});
+        old.cache
+            .insert("error".to_string(), "getBlock", error, ttl)
+            .await;
+
+        let snapshot = export_snapshot(&old).await;
+        assert_eq!(snapshot.entries.len(), 2);
+
+        // Round-trips through its file format, with some time passing on the way.
+        let mut snapshot: Snapshot =
+            serde_json::from_slice(&serde_json::to_vec(&snapshot).unwrap()).unwrap();
+        snapshot.created_at_ms -= 1_000;
+
+        // Negative caching is off in the new instance, so the error stays behind.
+        let new = state();
+        assert_eq!(import_snapshot(&new, snapshot).await, Ok(1));
+        let entry = new
+            .cache
+            .export()
+            .await
+            .into_iter()
+            .find(|entry| entry.key == "block")
+            .unwrap();
+        assert_eq!(entry.value, json!({"slot": 1}));
+        assert!(entry.ttl <= ttl - Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_incompatible_snapshots_are_rejected() {
+        let old = state();
+        old.cache
+            .insert(
+                "slot".to_string(),
+                "getSlot",
+                json!(1),
+                Duration::from_secs(60),
+            )
+            .await;
+        let new = state();
+
+        let mut snapshot = export_snapshot(&old).await;
+        snapshot.normalization_version += 1;
+        assert!(import_snapshot(&new, snapshot).await.is_err());
+
+        let mut snapshot = export_snapshot(&old).await;
+        snapshot.privacy_mode = PrivacyMode::Balanced.to_string();
+        assert!(import_snapshot(&new, snapshot).await.is_err());
+
+        assert_eq!(new.cache.stats().await.entries, 0);
+    }
+}
@@ -0,0 +1,280 @@
+//! Cache TTL policy: how long a cached response stays fresh, per RPC method
+//! and commitment level, and how long negative answers are kept.
+
+use crate::commitment::Commitment;
+use crate::privacy_mode::PrivacyMode;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+#[derive(Clone, Debug)]
+pub struct TtlPolicies {
+    default: Duration,
+    methods: HashMap<String, Duration>,
+    /// Upper bound on the TTL per commitment; zero disables caching at that level.
+    commitment_caps: HashMap<Commitment, Duration>,
+}
+
+impl TtlPolicies {
+    /// Built-in TTLs for the methods `mode` caches; other methods use `default`.
+    pub fn for_mode(mode: PrivacyMode, default: Duration) -> Self {
+        let methods: &[(&str, u64)] = match mode {
+            // Strict caches longer so repeated reads reach upstream less often.
+            PrivacyMode::Strict => &[
+                ("getLatestBlockhash", 2_000),
+                ("getSlot", 1_000),
+                ("getBalance", 5_000),
+                ("getAccountInfo", 5_000),
+                ("getBlock", 3_600_000),
+                ("getTransaction", 3_600_000),
+            ],
+            PrivacyMode::Balanced => &[
+                ("getLatestBlockhash", 1_000),
+                ("getSlot", 1_000),
+                ("getBalance", 2_000),
+            ],
+            PrivacyMode::Dev => &[],
+        };
+
+        // Method TTLs describe finalized data; processed data can still be rolled back.
+        let commitment_caps = HashMap::from([
+            (Commitment::Processed, Duration::ZERO),
+            (Commitment::Confirmed, Duration::from_secs(5)),
+        ]);
+
+        Self {
+            default,
+            methods: methods
+                .iter()
+                .map(|(method, ms)| (method.to_string(), Duration::from_millis(*ms)))
+                .collect(),
+            commitment_caps,
+        }
+    }
+
+    pub fn with_overrides(mut self, overrides: HashMap<String, Duration>) -> Self {
+        self.methods.extend(overrides);
+        self
+    }
+
+    pub fn with_commitment_caps(mut self, caps: HashMap<Commitment, Duration>) -> Self {
+        self.commitment_caps.extend(caps);
+        self
+    }
+
+    pub fn for_method(&self, method: &str) -> Duration {
+        self.methods.get(method).copied().unwrap_or(self.default)
+    }
+
+    /// TTL for `method` read at `commitment`, or `None` when it must not be cached.
+    pub fn for_call(&self, method: &str, commitment: Commitment) -> Option<Duration> {
+        let ttl = match self.commitment_caps.get(&commitment) {
+            Some(cap) => self.for_method(method).min(*cap),
+            None => self.for_method(method),
+        };
+        (!ttl.is_zero()).then_some(ttl)
+    }
+}
+
+/// Short-lived caching of answers that say "there is nothing here": JSON-RPC
+/// errors with an allowlisted code and `null` results such as a missing account.
+#[derive(Clone, Debug)]
+pub struct NegativeCaching {
+    /// Zero disables negative caching.
+    ttl: Duration,
+    error_codes: HashSet<i64>,
+}
+
+impl NegativeCaching {
+    /// Error codes Solana nodes return for requests that fail the same way every
+    /// time: invalid params (e.g. a malformed signature) and skipped slots.
+    pub const DEFAULT_ERROR_CODES: [i64; 3] = [-32602, -32007, -32009];
+
+    pub fn new(ttl: Duration, error_codes: HashSet<i64>) -> Self {
+        Self { ttl, error_codes }
+    }
+
+    /// Whether negative answers are stored under their own TTL.
+    pub fn is_enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    /// TTL for storing `response` to a `method` call cacheable for `ttl`, or
+    /// `None` when it must not be stored. Negative answers never outlive the
+    /// method's TTL.
+    pub fn response_ttl(&self, method: &str, response: &Value, ttl: Duration) -> Option<Duration> {
+        if let Some(error) = response.get("error") {
+            let code = error.get("code").and_then(Value::as_i64)?;
+            let cacheable = self.is_enabled() && self.error_codes.contains(&code);
+            return cacheable.then(|| self.ttl.min(ttl));
+        }
+        if is_negative(response) {
+            if self.is_enabled() {
+                return Some(self.ttl.min(ttl));
+            }
+            // A transaction that is not found yet may land later, so its `null`
+            // must not be kept for the long TTL of a found one.
+            if method == "getTransaction" {
+                return None;
+            }
+            // Other `null` results keep the method's TTL as before.
+        }
+        Some(ttl)
+    }
+}
+
+/// Whether `response` is an error or a `null` result, either bare (e.g.
+/// `getTransaction`) or as the `value` of an RPC context (e.g. `getAccountInfo`).
+pub fn is_negative(response: &Value) -> bool {
+    if response.get("error").is_some() {
+        return true;
+    }
+    match response.get("result") {
+        Some(Value::Null) => true,
+        Some(Value::Object(result)) if result.contains_key("context") => {
+            result.get("value").is_some_and(Value::is_null)
+        }
+        _ => false,
+    }
+}
+
+/// Parses comma-separated JSON-RPC error codes, e.g. `-32602,-32009`.
+pub fn parse_error_codes(value: &str) -> Result<HashSet<i64>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|code| !code.is_empty())
+        .map(|code| {
+            code.parse()
+                .map_err(|_| format!("invalid error code {}", code))
+        })
+        .collect()
+}
+
+/// Parses `name=ms` pairs separated by commas, e.g. `getSlot=500,getBlock=60000`.
+pub fn parse_ttl_overrides(value: &str) -> Result<HashMap<String, Duration>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (method, ms) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected method=ms, got {}", pair))?;
+            let ms: u64 = ms
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid TTL for {}: {}", method.trim(), ms.trim()))?;
+            Ok((method.trim().to_string(), Duration::from_millis(ms)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_mode_defaults_and_fallback() {
+        let policies = TtlPolicies::for_mode(PrivacyMode::Strict, Duration::from_secs(5));
+
+        assert_eq!(
+            policies.for_method("getLatestBlockhash"),
+            Duration::from_secs(2)
+        );
+        assert_eq!(policies.for_method("getBlock"), Duration::from_secs(3_600));
+        assert_eq!(policies.for_method("getEpochInfo"), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_overrides_replace_builtin_ttls() {
+        let overrides = parse_ttl_overrides("getSlot=250, getEpochInfo=30000").unwrap();
+        let policies = TtlPolicies::for_mode(PrivacyMode::Balanced, Duration::from_secs(5))
+            .with_overrides(overrides);
+
+        assert_eq!(policies.for_method("getSlot"), Duration::from_millis(250));
+        assert_eq!(policies.for_method("getEpochInfo"), Duration::from_secs(30));
+        assert_eq!(policies.for_method("getBalance"), Duration::from_secs(2));
+        assert!(parse_ttl_overrides("getSlot").is_err());
+        assert!(parse_ttl_overrides("getSlot=soon").is_err());
+    }
+
+    #[test]
+    fn test_commitment_caps_ttl() {
+        let policies = TtlPolicies::for_mode(PrivacyMode::Strict, Duration::from_secs(5));
+
+        assert_eq!(
+            policies.for_call("getAccountInfo", Commitment::Processed),
+            None
+        );
+        assert_eq!(
+            policies.for_call("getBlock", Commitment::Confirmed),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            policies.for_call("getBlock", Commitment::Finalized),
+            Some(Duration::from_secs(3_600))
+        );
+
+        let policies = policies.with_commitment_caps(HashMap::from([(
+            Commitment::Processed,
+            Duration::from_millis(400),
+        )]));
+        assert_eq!(
+            policies.for_call("getSlot", Commitment::Processed),
+            Some(Duration::from_millis(400))
+        );
+    }
+
+    #[test]
+    fn test_negative_answers_use_negative_ttl() {
+        let policy = NegativeCaching::new(
+            Duration::from_secs(1),
+            parse_error_codes("-32602, -32009").unwrap(),
+        );
+        let ttl = Duration::from_secs(60);
+        let error = |code: i64| json!({"jsonrpc": "2.0", "error": {"code": code, "message": "x"}});
+        let missing_account = json!({"result": {"context": {"slot": 1}, "value": null}});
+
+        assert_eq!(
+            policy.response_ttl("getAccountInfo", &error(-32602), ttl),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            policy.response_ttl("getAccountInfo", &error(-32004), ttl),
+            None
+        );
+        assert_eq!(
+            policy.response_ttl("getAccountInfo", &missing_account, ttl),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            policy.response_ttl(
+                "getAccountInfo",
+                &json!({"result": null}),
+                Duration::from_millis(500)
+            ),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            policy.response_ttl("getAccountInfo", &json!({"result": 5}), ttl),
+            Some(ttl)
+        );
+        assert!(parse_error_codes("-32602,oops").is_err());
+
+        let disabled = NegativeCaching::new(Duration::ZERO, HashSet::new());
+        assert_eq!(
+            disabled.response_ttl("getAccountInfo", &error(-32602), ttl),
+            None
+        );
+        assert_eq!(
+            disabled.response_ttl("getAccountInfo", &missing_account, ttl),
+            Some(ttl)
+        );
+        assert_eq!(
+            disabled.response_ttl("getTransaction", &json!({"result": null}), ttl),
+            None
+        );
+    }
+}
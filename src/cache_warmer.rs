@@ -0,0 +1,368 @@
+//! Proactive cache warming: hot requests are refreshed upstream shortly before
+//! their entries expire, so clients asking for them are answered from the cache.
+//!
+//! The hot set is the configured request templates plus, optionally, the
+//! requests with the most cache hits in the last learning window.
+
+use crate::config::Config;
+use crate::log_events::LogEvent;
+use crate::proxy::{cache_key, warm_cache};
+use crate::server::AppState;
+use crate::slot_watcher::is_invalidated_every_slot;
+use futures_util::future::join_all;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often the schedule is checked for due refreshes.
+const TICK: Duration = Duration::from_millis(100);
+/// Delay before retrying a refresh that failed or returned nothing cacheable.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const LEARN_WINDOW: Duration = Duration::from_secs(60);
+/// Hits within one window before a request can be learned as hot.
+const LEARN_MIN_HITS: u64 = 10;
+/// Distinct requests counted per window, bounding memory under diverse traffic.
+const HOT_CANDIDATES: usize = 4096;
+
+#[derive(Clone, Debug, Default)]
+pub struct WarmingConfig {
+    /// JSON-RPC requests kept warm; the `id` is irrelevant.
+    pub templates: Vec<Value>,
+    /// How long before expiry an entry is refreshed.
+    pub lead: Duration,
+    /// How many of the most hit requests are learned as hot; zero disables learning.
+    pub learn_top: usize,
+}
+
+impl WarmingConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.templates.is_empty() || self.learn_top > 0
+    }
+}
+
+/// Shared with the request path, which reports cache hits for learning.
+pub struct CacheWarmer {
+    config: WarmingConfig,
+    hits: Mutex<HashMap<String, HotRequest>>,
+}
+
+struct HotRequest {
+    payload: Value,
+    hits: u64,
+}
+
+impl CacheWarmer {
+    pub fn new(config: WarmingConfig) -> Self {
+        Self {
+            config,
+            hits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts a cache hit on `hash`, whose request was `payload`.
+    pub fn record_hit(&self, hash: &str, payload: &Value) {
+        if self.config.learn_top == 0 {
+            return;
+        }
+        let mut hits = self.hits.lock().expect("warmer hits lock");
+        if let Some(hot) = hits.get_mut(hash) {
+            hot.hits += 1;
+        } else if hits.len() < HOT_CANDIDATES {
+            // Client-chosen ids never leave the request they came with.
+            let mut payload = payload.clone();
+            if let Value::Object(ref mut map) = payload {
+                map.remove("id");
+            }
+            hits.insert(hash.to_string(), HotRequest { payload, hits: 1 });
+        }
+    }
+
+    /// The most hit requests of the window that just ended; starts a new window.
+    fn take_hot(&self) -> Vec<(String, Value)> {
+        let hits = std::mem::take(&mut *self.hits.lock().expect("warmer hits lock"));
+        let mut hot: Vec<(String, HotRequest)> = hits
+            .into_iter()
+            .filter(|(_, hot)| hot.hits >= LEARN_MIN_HITS)
+            .collect();
+        hot.sort_by(|a, b| b.1.hits.cmp(&a.1.hits).then_with(|| a.0.cmp(&b.0)));
+        hot.into_iter()
+            .take(self.config.learn_top)
+            .map(|(hash, hot)| (hash, hot.payload))
+            .collect()
+    }
+}
+
+struct Scheduled {
+    payload: Value,
+    method: String,
+    due: Instant,
+    learned: bool,
+}
+
+/// Refreshes every scheduled request when its entry is about to expire.
+pub async fn run_cache_warming(state: AppState) {
+    let Some(warmer) = state.warmer.clone() else {
+        return;
+    };
+
+    let now = Instant::now();
+    let mut schedule = schedule_templates(&state.config, &warmer.config.templates, now);
+    tracing::info!(
+        templates = schedule.len(),
+        learn_top = warmer.config.learn_top,
+        "cache warming started"
+    );
+
+    let mut ticker = tokio::time::interval(TICK);
+    let mut next_learn = now + LEARN_WINDOW;
+    loop {
+        ticker.tick().await;
+        let now = Instant::now();
+
+        if warmer.config.learn_top > 0 && now >= next_learn {
+            next_learn = now + LEARN_WINDOW;
+            let hot = warmer
+                .take_hot()
+                .into_iter()
+                .filter(|(_, payload)| warm_refusal(&state.config, method_of(payload)).is_none())
+                .collect();
+            learn(&mut schedule, hot, now);
+        }
+
+        let due: Vec<(String, Value)> = schedule
+            .iter()
+            .filter(|(_, scheduled)| scheduled.due <= now)
+            .map(|(hash, scheduled)| (hash.clone(), scheduled.payload.clone()))
+            .collect();
+        if due.is_empty() {
+            continue;
+        }
+
+        let refreshed = join_all(due.into_iter().map(|(hash, payload)| {
+            let state = state.clone();
+            async move { (hash, warm_cache(&state, payload).await) }
+        }))
+        .await;
+
+        let now = Instant::now();
+        for (hash, outcome) in refreshed {
+            let Some(scheduled) = schedule.get_mut(&hash) else {
+                continue;
+            };
+            scheduled.due = match outcome {
+                Ok(Some(ttl)) => {
+                    state
+                        .log_state
+                        .record(
+                            LogEvent::new("INFO", "CACHE_WARMED")
+                                .with_hash(hash.clone())
+                                .with_method(scheduled.method.clone())
+                                .with_ttl(ttl),
+                        )
+                        .await;
+                    now + refresh_after(ttl, warmer.config.lead)
+                }
+                Ok(None) => now + RETRY_DELAY,
+                Err(err) => {
+                    tracing::debug!(error = %err, hash = %hash, "cache warming failed");
+                    now + RETRY_DELAY
+                }
+            };
+        }
+    }
+}
+
+/// Why requests for `method` are not warmed, if they are not: the method
+/// policy refuses them, or slot invalidation drops their entries on every slot
+/// (about every 400 ms), long before a warmed entry could be hit.
+fn warm_refusal(config: &Config, method: &str) -> Option<String> {
+    if let Some(reason) = config.method_policy.rejection(method) {
+        return Some(reason);
+    }
+    if is_invalidated_every_slot(config, method) {
+        return Some(format!(
+            "{} entries are dropped on every slot while slot invalidation runs",
+            method
+        ));
+    }
+    None
+}
+
+/// Schedules the configured templates, due at `now`. Templates that are
+/// refused or that the privacy mode never caches could not be warmed, so they
+/// are skipped with a warning.
+fn schedule_templates(
+    config: &Config,
+    templates: &[Value],
+    now: Instant,
+) -> HashMap<String, Scheduled> {
+    let mut schedule = HashMap::new();
+    for template in templates {
+        if let Some(reason) = warm_refusal(config, method_of(template)) {
+            tracing::warn!(reason = %reason, "warm request refused, skipping");
+            continue;
+        }
+        match cache_key(config, template.clone()) {
+            Ok(Some(hash)) => {
+                schedule.insert(hash, scheduled(template.clone(), now, false));
+            }
+            Ok(None) => {
+                tracing::warn!(method = %method_of(template), privacy_mode = %config.privacy_mode, "warm method is never cached in this privacy mode, skipping");
+            }
+            Err(err) => {
+                tracing::warn!(error = %err, "invalid warm request, skipping");
+            }
+        }
+    }
+    schedule
+}
+
+/// Replaces the learned part of the schedule with `hot`; configured requests stay.
+fn learn(schedule: &mut HashMap<String, Scheduled>, hot: Vec<(String, Value)>, now: Instant) {
+    schedule.retain(|hash, scheduled| {
+        !scheduled.learned || hot.iter().any(|(hot_hash, _)| hot_hash == hash)
+    });
+    for (hash, payload) in hot {
+        schedule
+            .entry(hash)
+            .or_insert_with(|| scheduled(payload, now, true));
+    }
+    let learned = schedule
+        .values()
+        .filter(|scheduled| scheduled.learned)
+        .count();
+    tracing::info!(learned, "updated learned warm requests");
+}
+
+fn scheduled(payload: Value, due: Instant, learned: bool) -> Scheduled {
+    Scheduled {
+        method: method_of(&payload).to_string(),
+        payload,
+        due,
+        learned,
+    }
+}
+
+fn method_of(payload: &Value) -> &str {
+    payload.get("method").and_then(Value::as_str).unwrap_or("")
+}
+
+/// Delay until the next refresh of an entry cached for `ttl`: `lead` before
+/// it expires, but never sooner than half its lifetime.
+fn refresh_after(ttl: Duration, lead: Duration) -> Duration {
+    ttl.saturating_sub(lead).max(ttl / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::method_policy::MethodPolicy;
+    use crate::privacy_mode::PrivacyMode;
+    use crate::proxy::tests::test_state;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_refresh_happens_lead_before_expiry() {
+        let lead = Duration::from_millis(300);
+        assert_eq!(
+            refresh_after(Duration::from_secs(2), lead),
+            Duration::from_millis(1_700)
+        );
+        assert_eq!(
+            refresh_after(Duration::from_millis(400), lead),
+            Duration::from_millis(200)
+        );
+    }
+
+    #[test]
+    fn test_uncacheable_templates_are_skipped() {
+        let state = test_state("http://127.0.0.1:1".to_string(), PrivacyMode::Strict);
+        let templates = vec![
+            json!({"jsonrpc": "2.0", "method": "getSlot"}),
+            json!({"jsonrpc": "2.0", "method": "getEpochInfo"}),
+            json!({"jsonrpc": "2.0", "method": 5}),
+        ];
+
+        let schedule = schedule_templates(&state.config, &templates, Instant::now());
+        let methods: Vec<_> = schedule.values().map(|s| s.method.as_str()).collect();
+        assert_eq!(methods, vec!["getSlot"]);
+    }
+
+    #[test]
+    fn test_templates_refused_by_policy_are_skipped() {
+        let mut state = test_state("http://127.0.0.1:1".to_string(), PrivacyMode::Strict);
+        let mut config = (*state.config).clone();
+        config.method_policy = MethodPolicy::for_mode(PrivacyMode::Strict).with_overrides(
+            serde_json::from_value(json!({"deny": ["getLatestBlockhash"]})).unwrap(),
+        );
+        state.config = Arc::new(config);
+        let templates = vec![
+            json!({"jsonrpc": "2.0", "method": "getSlot"}),
+            json!({"jsonrpc": "2.0", "method": "getLatestBlockhash"}),
+        ];
+
+        let schedule = schedule_templates(&state.config, &templates, Instant::now());
+        let methods: Vec<_> = schedule.values().map(|s| s.method.as_str()).collect();
+        assert_eq!(methods, vec!["getSlot"]);
+    }
+
+    #[test]
+    fn test_slot_sensitive_templates_are_skipped_under_slot_invalidation() {
+        let mut state = test_state("http://127.0.0.1:1".to_string(), PrivacyMode::Strict);
+        let templates = vec![
+            json!({"jsonrpc": "2.0", "method": "getSlot"}),
+            json!({"jsonrpc": "2.0", "method": "getBalance", "params": ["acct"]}),
+        ];
+        assert_eq!(
+            schedule_templates(&state.config, &templates, Instant::now()).len(),
+            2
+        );
+
+        let mut config = (*state.config).clone();
+        config.quicknode_ws_url = Some("ws://127.0.0.1:1".to_string());
+        config.slot_invalidation = true;
+        state.config = Arc::new(config);
+        let schedule = schedule_templates(&state.config, &templates, Instant::now());
+        let methods: Vec<_> = schedule.values().map(|s| s.method.as_str()).collect();
+        assert_eq!(methods, vec!["getBalance"]);
+    }
+
+    #[test]
+    fn test_most_hit_requests_are_learned() {
+        let warmer = CacheWarmer::new(WarmingConfig {
+            templates: Vec::new(),
+            lead: Duration::ZERO,
+            learn_top: 1,
+        });
+        let slot = json!({"jsonrpc": "2.0", "id": 7, "method": "getSlot"});
+        let epoch = json!({"jsonrpc": "2.0", "id": 8, "method": "getEpochInfo"});
+        for _ in 0..LEARN_MIN_HITS + 1 {
+            warmer.record_hit("slot", &slot);
+        }
+        for _ in 0..LEARN_MIN_HITS {
+            warmer.record_hit("epoch", &epoch);
+        }
+
+        let hot = warmer.take_hot();
+        assert_eq!(
+            hot,
+            vec![(
+                "slot".to_string(),
+                json!({"jsonrpc": "2.0", "method": "getSlot"})
+            )]
+        );
+        // Each window starts from zero.
+        assert!(warmer.take_hot().is_empty());
+
+        let now = Instant::now();
+        let mut schedule =
+            HashMap::from([("epoch".to_string(), scheduled(epoch.clone(), now, false))]);
+        learn(&mut schedule, hot, now);
+        assert!(schedule["slot"].learned);
+        learn(&mut schedule, Vec::new(), now);
+        assert_eq!(schedule.len(), 1);
+        assert!(!schedule["epoch"].learned);
+    }
+}
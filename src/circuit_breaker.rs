@@ -0,0 +1,247 @@
+//! Per-upstream circuit breaker driven by recent request outcomes.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug)]
+pub struct CircuitBreakerConfig {
+    /// Number of recent outcomes the error rate is computed over.
+    pub window: usize,
+    /// Outcomes required in the window before the circuit may open.
+    pub min_requests: usize,
+    /// Failure ratio (0.0..=1.0) at which the circuit opens.
+    pub failure_ratio: f64,
+    /// How long an open circuit rejects traffic before allowing a trial request.
+    pub open_for: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            window: 20,
+            min_requests: 5,
+            failure_ratio: 0.5,
+            open_for: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = match self {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half-open",
+        };
+        write!(f, "{}", value)
+    }
+}
+
+/// Availability changes worth surfacing to operators.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitEvent {
+    /// The circuit opened; the upstream is skipped during selection.
+    Down,
+    /// The circuit closed again after a successful trial or probe.
+    Up,
+}
+
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    inner: Mutex<BreakerState>,
+}
+
+struct BreakerState {
+    state: CircuitState,
+    // true = failure, newest at the back.
+    outcomes: VecDeque<bool>,
+    opened_at: Option<Instant>,
+    trial_started: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            inner: Mutex::new(BreakerState {
+                state: CircuitState::Closed,
+                outcomes: VecDeque::with_capacity(config.window),
+                opened_at: None,
+                trial_started: None,
+            }),
+            config,
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.inner.lock().expect("circuit breaker lock").state
+    }
+
+    /// Whether selection may route a request here, without claiming a trial slot.
+    pub fn is_available(&self) -> bool {
+        let guard = self.inner.lock().expect("circuit breaker lock");
+        match guard.state {
+            CircuitState::Closed => true,
+            CircuitState::Open => self.open_elapsed(&guard),
+            CircuitState::HalfOpen => self.trial_slot_free(&guard),
+        }
+    }
+
+    /// Claims the right to send a request, moving an expired open circuit to half-open.
+    pub fn try_acquire(&self) -> bool {
+        let mut guard = self.inner.lock().expect("circuit breaker lock");
+        match guard.state {
+            CircuitState::Closed => true,
+            CircuitState::Open if self.open_elapsed(&guard) => {
+                guard.state = CircuitState::HalfOpen;
+                guard.trial_started = Some(Instant::now());
+                true
+            }
+            CircuitState::HalfOpen if self.trial_slot_free(&guard) => {
+                guard.trial_started = Some(Instant::now());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn record_success(&self) -> Option<CircuitEvent> {
+        let mut guard = self.inner.lock().expect("circuit breaker lock");
+        match guard.state {
+            CircuitState::Closed => {
+                self.push_outcome(&mut guard, false);
+                None
+            }
+            CircuitState::HalfOpen => Some(self.close(&mut guard)),
+            // Late successes from before the circuit opened do not count as recovery.
+            CircuitState::Open if self.open_elapsed(&guard) => Some(self.close(&mut guard)),
+            CircuitState::Open => None,
+        }
+    }
+
+    pub fn record_failure(&self) -> Option<CircuitEvent> {
+        let mut guard = self.inner.lock().expect("circuit breaker lock");
+        match guard.state {
+            CircuitState::Closed => {
+                self.push_outcome(&mut guard, true);
+                if self.should_open(&guard) {
+                    self.open(&mut guard);
+                    Some(CircuitEvent::Down)
+                } else {
+                    None
+                }
+            }
+            // A failed trial (or probe) keeps the circuit open for another period.
+            CircuitState::HalfOpen | CircuitState::Open => {
+                self.open(&mut guard);
+                None
+            }
+        }
+    }
+
+    fn push_outcome(&self, guard: &mut BreakerState, failed: bool) {
+        if guard.outcomes.len() >= self.config.window.max(1) {
+            guard.outcomes.pop_front();
+        }
+        guard.outcomes.push_back(failed);
+    }
+
+    fn should_open(&self, guard: &BreakerState) -> bool {
+        let total = guard.outcomes.len();
+        if total < self.config.min_requests.max(1) {
+            return false;
+        }
+        let failures = guard.outcomes.iter().filter(|&&failed| failed).count();
+        failures as f64 / total as f64 >= self.config.failure_ratio
+    }
+
+    fn open(&self, guard: &mut BreakerState) {
+        guard.state = CircuitState::Open;
+        guard.opened_at = Some(Instant::now());
+        guard.trial_started = None;
+    }
+
+    fn close(&self, guard: &mut BreakerState) -> CircuitEvent {
+        guard.state = CircuitState::Closed;
+        guard.outcomes.clear();
+        guard.opened_at = None;
+        guard.trial_started = None;
+        CircuitEvent::Up
+    }
+
+    fn open_elapsed(&self, guard: &BreakerState) -> bool {
+        guard
+            .opened_at
+            .map(|opened_at| opened_at.elapsed() >= self.config.open_for)
+            .unwrap_or(true)
+    }
+
+    fn trial_slot_free(&self, guard: &BreakerState) -> bool {
+        // A trial that never reported back (e.g. the client went away) expires.
+        guard
+            .trial_started
+            .map(|started| started.elapsed() >= self.config.open_for)
+            .unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(open_for: Duration) -> CircuitBreaker {
+        CircuitBreaker::new(CircuitBreakerConfig {
+            window: 4,
+            min_requests: 4,
+            failure_ratio: 0.5,
+            open_for,
+        })
+    }
+
+    #[test]
+    fn test_opens_once_error_rate_crosses_threshold() {
+        let breaker = breaker(Duration::from_secs(60));
+
+        assert_eq!(breaker.record_success(), None);
+        assert_eq!(breaker.record_success(), None);
+        assert_eq!(breaker.record_failure(), None);
+        assert_eq!(breaker.record_failure(), Some(CircuitEvent::Down));
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.is_available());
+        assert!(!breaker.try_acquire());
+    }
+
+    #[test]
+    fn test_half_open_allows_single_trial_and_closes_on_success() {
+        let breaker = breaker(Duration::ZERO);
+        for _ in 0..4 {
+            breaker.record_failure();
+        }
+
+        // Zero open duration: the next acquire is the half-open trial.
+        assert!(breaker.try_acquire());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert_eq!(breaker.record_success(), Some(CircuitEvent::Up));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_failed_trial_reopens_without_new_event() {
+        let breaker = breaker(Duration::from_millis(0));
+        for _ in 0..4 {
+            breaker.record_failure();
+        }
+
+        assert!(breaker.try_acquire());
+        assert_eq!(breaker.record_failure(), None);
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+}
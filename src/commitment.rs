@@ -0,0 +1,137 @@
+//! Solana commitment levels as they appear in request config objects.
+
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Commitment {
+    Processed,
+    Confirmed,
+    Finalized,
+}
+
+impl FromStr for Commitment {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        // Deprecated names are still accepted by Solana nodes.
+        match value {
+            "processed" | "recent" => Ok(Commitment::Processed),
+            "confirmed" | "single" | "singleGossip" => Ok(Commitment::Confirmed),
+            "finalized" | "root" | "max" => Ok(Commitment::Finalized),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Commitment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = match self {
+            Commitment::Processed => "processed",
+            Commitment::Confirmed => "confirmed",
+            Commitment::Finalized => "finalized",
+        };
+        write!(f, "{}", value)
+    }
+}
+
+/// Commitment stated in the request's trailing config object, if any.
+pub fn request_commitment(payload: &Value) -> Option<Commitment> {
+    config_object(payload)?
+        .get("commitment")?
+        .as_str()?
+        .parse()
+        .ok()
+}
+
+/// Rewrites the commitment into one canonical form for hashing: an omitted
+/// commitment and an explicit `default` (or a deprecated alias of either) hash
+/// the same, and an emptied config object is dropped. An empty `params` array
+/// hashes like no `params` at all, whether or not a config object was dropped.
+pub fn canonicalize_commitment(mut payload: Value, default: Commitment) -> Value {
+    let commitment = request_commitment(&payload);
+    let Some(params) = payload.get_mut("params").and_then(Value::as_array_mut) else {
+        return payload;
+    };
+    if let (Some(commitment), Some(Value::Object(config))) = (commitment, params.last_mut()) {
+        if commitment == default {
+            config.remove("commitment");
+        } else {
+            config.insert(
+                "commitment".to_string(),
+                Value::String(commitment.to_string()),
+            );
+        }
+        if config.is_empty() {
+            params.pop();
+        }
+    }
+    if params.is_empty() {
+        if let Value::Object(map) = &mut payload {
+            map.remove("params");
+        }
+    }
+    payload
+}
+
+fn config_object(payload: &Value) -> Option<&serde_json::Map<String, Value>> {
+    // Solana methods take their config object as the last positional parameter.
+    payload.get("params")?.as_array()?.last()?.as_object()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_request_commitment_reads_trailing_config() {
+        let read = json!({"method": "getBalance", "params": ["addr", {"commitment": "processed"}]});
+        let alias = json!({"method": "getSlot", "params": [{"commitment": "max"}]});
+        let none = json!({"method": "getBalance", "params": ["addr"]});
+
+        assert_eq!(request_commitment(&read), Some(Commitment::Processed));
+        assert_eq!(request_commitment(&alias), Some(Commitment::Finalized));
+        assert_eq!(request_commitment(&none), None);
+    }
+
+    #[test]
+    fn test_default_commitment_hashes_like_omitted() {
+        let explicit = json!({"method": "getSlot", "params": [{"commitment": "finalized"}]});
+        let omitted = json!({"method": "getSlot"});
+        let account = json!({
+            "method": "getAccountInfo",
+            "params": ["addr", {"commitment": "root", "encoding": "base64"}]
+        });
+
+        let finalized = Commitment::Finalized;
+        assert_eq!(canonicalize_commitment(explicit, finalized), omitted);
+        assert_eq!(
+            canonicalize_commitment(account, finalized),
+            json!({"method": "getAccountInfo", "params": ["addr", {"encoding": "base64"}]})
+        );
+    }
+
+    #[test]
+    fn test_empty_params_hash_like_default_commitment() {
+        let empty = json!({"method": "getSlot", "params": []});
+        let explicit = json!({"method": "getSlot", "params": [{"commitment": "finalized"}]});
+
+        let finalized = Commitment::Finalized;
+        assert_eq!(
+            canonicalize_commitment(empty, finalized),
+            canonicalize_commitment(explicit, finalized)
+        );
+    }
+
+    #[test]
+    fn test_non_default_commitment_is_kept_canonical() {
+        let alias = json!({"method": "getSlot", "params": [{"commitment": "singleGossip"}]});
+
+        assert_eq!(
+            canonicalize_commitment(alias, Commitment::Finalized),
+            json!({"method": "getSlot", "params": [{"commitment": "confirmed"}]})
+        );
+    }
+}
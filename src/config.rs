@@ -1,23 +1,156 @@
-//! Runtime configuration sourced from environment variables.
+//! Runtime configuration sourced from environment variables and an optional
+//! JSON config file for structured settings.
 
+use crate::cache::{CacheBackend, CacheLimits, DiskConfig, RedisConfig};
+use crate::cache_ttl::{parse_error_codes, parse_ttl_overrides, NegativeCaching, TtlPolicies};
+use crate::cache_warmer::WarmingConfig;
+use crate::circuit_breaker::CircuitBreakerConfig;
+use crate::commitment::Commitment;
+use crate::method_policy::{MethodPolicy, MethodPolicyOverride};
 use crate::privacy_mode::PrivacyMode;
+use crate::retry::{BackoffConfig, RetryPolicies, RetryPolicyOverride};
+use crate::routing::{RouteRule, RouteTable, DEFAULT_POOL};
+use crate::upstream::{SelectionStrategy, UpstreamConfig};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
 use std::env;
+use std::fs;
+use std::path::PathBuf;
 use std::time::Duration;
 
 #[derive(Clone)]
 pub struct Config {
-    pub quicknode_url: String,
+    /// Upstream endpoints per pool name; always contains the default pool.
+    pub upstream_pools: HashMap<String, Vec<UpstreamConfig>>,
+    pub routes: RouteTable,
+    pub upstream_strategy: SelectionStrategy,
+    pub circuit_breaker: CircuitBreakerConfig,
+    pub health_check_interval: Option<Duration>,
     pub quicknode_ws_url: Option<String>,
+    /// Invalidate slot-sensitive cache entries from a `slotSubscribe` stream.
+    pub slot_invalidation: bool,
+    /// Invalidate cached account reads from `accountSubscribe` notifications.
+    pub account_invalidation: bool,
+    /// Most accounts watched at once; the least recently used is dropped first.
+    pub account_watch_limit: usize,
     pub privacy_mode: PrivacyMode,
-    pub cache_ttl: Duration,
+    /// Methods refused before anything is sent upstream.
+    pub method_policy: MethodPolicy,
+    pub cache_ttls: TtlPolicies,
+    pub negative_cache: NegativeCaching,
+    /// Commitment the cluster applies when a request does not state one.
+    pub default_commitment: Commitment,
+    pub cache_limits: CacheLimits,
+    pub cache_backend: CacheBackend,
+    /// How long an expired entry may still be served while one refresh runs.
+    pub cache_stale_grace: Duration,
+    /// Serialized size from which entries are kept compressed in memory.
+    pub cache_compress_min_bytes: Option<usize>,
+    pub cache_warming: WarmingConfig,
+    /// Snapshot imported into the cache at startup.
+    pub cache_snapshot_file: Option<PathBuf>,
+    /// How often expired entries are purged; `None` leaves them to lazy eviction.
+    pub cache_sweep_interval: Option<Duration>,
     pub request_timeout: Duration,
-    pub retry_attempts: usize,
+    /// Largest upstream response body the gateway relays or decodes.
+    pub max_response_bytes: u64,
+    pub retry_policies: RetryPolicies,
+    pub retry_backoff: BackoffConfig,
     pub bind_addr: String,
+    /// Bearer token for the `/admin` endpoints; they are not served without one.
+    pub admin_token: Option<String>,
+}
+
+/// Settings that do not fit in a single environment variable.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    upstreams: Vec<UpstreamConfig>,
+    pools: HashMap<String, Vec<UpstreamConfig>>,
+    routes: Vec<RouteRule>,
+    retry_policies: HashMap<String, RetryPolicyOverride>,
+    /// Per-method cache TTLs in milliseconds.
+    cache_ttl_ms: HashMap<String, u64>,
+    /// Per-commitment TTL caps in milliseconds; `0` disables caching.
+    commitment_max_ttl_ms: HashMap<String, u64>,
+    /// JSON-RPC requests kept warm in the cache.
+    warm_requests: Vec<serde_json::Value>,
+    /// Allow and deny lists replacing the privacy mode's defaults.
+    method_policy: MethodPolicyOverride,
+}
+
+impl FileConfig {
+    fn load() -> Self {
+        let Ok(path) = env::var("GATEWAY_CONFIG_FILE") else {
+            return Self::default();
+        };
+
+        let contents = fs::read_to_string(&path)
+            .unwrap_or_else(|err| panic!("failed to read config file {}: {}", path, err));
+        serde_json::from_str(&contents)
+            .unwrap_or_else(|err| panic!("invalid config file {}: {}", path, err))
+    }
 }
 
 impl Config {
     pub fn from_env() -> Self {
-        let quicknode_url = env::var("QUICKNODE_RPC_URL").expect("QUICKNODE_RPC_URL must be set");
+        let file = FileConfig::load();
+
+        // The config file's upstream list wins; otherwise fall back to the single URL.
+        let upstreams = if file.upstreams.is_empty() {
+            let quicknode_url = env::var("QUICKNODE_RPC_URL")
+                .expect("QUICKNODE_RPC_URL must be set when no upstreams are configured");
+            vec![UpstreamConfig::from_url(quicknode_url)]
+        } else {
+            file.upstreams
+        };
+
+        // `upstreams` is the default pool; named pools only serve routed methods.
+        let mut upstream_pools = file.pools;
+        upstream_pools.insert(DEFAULT_POOL.to_string(), upstreams);
+        for (name, pool) in &upstream_pools {
+            assert!(!pool.is_empty(), "upstream pool {} has no upstreams", name);
+        }
+        for rule in &file.routes {
+            assert!(
+                upstream_pools.contains_key(&rule.pool),
+                "route targets unknown upstream pool {}",
+                rule.pool
+            );
+        }
+
+        let upstream_strategy = env::var("UPSTREAM_STRATEGY")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(SelectionStrategy::Weighted);
+
+        let breaker_defaults = CircuitBreakerConfig::default();
+        let circuit_breaker = CircuitBreakerConfig {
+            window: env::var("CIRCUIT_WINDOW")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(breaker_defaults.window),
+            min_requests: env::var("CIRCUIT_MIN_REQUESTS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(breaker_defaults.min_requests),
+            failure_ratio: env::var("CIRCUIT_FAILURE_RATIO")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(breaker_defaults.failure_ratio),
+            open_for: env::var("CIRCUIT_OPEN_MS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(breaker_defaults.open_for),
+        };
+
+        // Zero disables active health checks.
+        let health_check_interval_ms: u64 = env::var("HEALTH_CHECK_INTERVAL_MS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(10_000);
 
         let privacy_mode = env::var("PRIVACY_MODE")
             .ok()
@@ -26,31 +159,247 @@ impl Config {
 
         let quicknode_ws_url = env::var("QUICKNODE_WS_URL").ok();
 
+        // Only takes effect when a WebSocket endpoint is configured.
+        let slot_invalidation = env::var("SLOT_INVALIDATION")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(true);
+
+        // Opt-in: one upstream subscription per hot account.
+        let account_invalidation = env::var("ACCOUNT_INVALIDATION")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(false);
+        let account_watch_limit = env::var("ACCOUNT_WATCH_LIMIT")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(1_000);
+
         let cache_ttl_seconds: u64 = env::var("CACHE_TTL_SECONDS")
             .ok()
             .and_then(|value| value.parse().ok())
             .unwrap_or(5);
 
+        // Method TTLs: mode defaults, then the config file, then the environment.
+        let file_ttls = file
+            .cache_ttl_ms
+            .into_iter()
+            .map(|(method, ms)| (method, Duration::from_millis(ms)))
+            .collect();
+        let env_ttls = env::var("CACHE_METHOD_TTL_MS")
+            .map(|value| {
+                parse_ttl_overrides(&value)
+                    .unwrap_or_else(|err| panic!("invalid CACHE_METHOD_TTL_MS: {}", err))
+            })
+            .unwrap_or_default();
+        let mut commitment_caps: HashMap<String, Duration> = file
+            .commitment_max_ttl_ms
+            .into_iter()
+            .map(|(commitment, ms)| (commitment, Duration::from_millis(ms)))
+            .collect();
+        if let Ok(value) = env::var("CACHE_COMMITMENT_MAX_TTL_MS") {
+            commitment_caps.extend(
+                parse_ttl_overrides(&value)
+                    .unwrap_or_else(|err| panic!("invalid CACHE_COMMITMENT_MAX_TTL_MS: {}", err)),
+            );
+        }
+        let commitment_caps = commitment_caps
+            .into_iter()
+            .map(|(commitment, cap)| {
+                let level = commitment
+                    .parse()
+                    .unwrap_or_else(|_| panic!("unknown commitment level {}", commitment));
+                (level, cap)
+            })
+            .collect();
+
+        let cache_ttls =
+            TtlPolicies::for_mode(privacy_mode, Duration::from_secs(cache_ttl_seconds))
+                .with_overrides(file_ttls)
+                .with_overrides(env_ttls)
+                .with_commitment_caps(commitment_caps);
+
+        // Zero disables negative caching; error codes must be listed to be cached.
+        let negative_cache_ttl_ms: u64 = env::var("NEGATIVE_CACHE_TTL_MS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0);
+        let negative_cache_error_codes = env::var("NEGATIVE_CACHE_ERROR_CODES")
+            .map(|value| {
+                parse_error_codes(&value)
+                    .unwrap_or_else(|err| panic!("invalid NEGATIVE_CACHE_ERROR_CODES: {}", err))
+            })
+            .unwrap_or_else(|_| NegativeCaching::DEFAULT_ERROR_CODES.into_iter().collect());
+        let negative_cache = NegativeCaching::new(
+            Duration::from_millis(negative_cache_ttl_ms),
+            negative_cache_error_codes,
+        );
+
+        // Solana nodes default to `finalized` when a request omits the commitment.
+        let default_commitment = env::var("CLUSTER_COMMITMENT")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(Commitment::Finalized);
+
+        let cache_max_entries: usize = env::var("CACHE_MAX_ENTRIES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(10_000);
+
+        let cache_max_bytes: usize = env::var("CACHE_MAX_BYTES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(64 * 1024 * 1024);
+
+        let cache_backend = match env::var("CACHE_BACKEND").as_deref() {
+            Err(_) | Ok("memory") => CacheBackend::Memory,
+            Ok("disk") => CacheBackend::Disk(DiskConfig {
+                dir: env::var("CACHE_DISK_DIR")
+                    .unwrap_or_else(|_| "cache".to_string())
+                    .into(),
+                max_bytes: env::var("CACHE_DISK_MAX_BYTES")
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(1024 * 1024 * 1024),
+                min_ttl: Duration::from_millis(
+                    env::var("CACHE_DISK_MIN_TTL_MS")
+                        .ok()
+                        .and_then(|value| value.parse().ok())
+                        .unwrap_or(60_000),
+                ),
+            }),
+            Ok("redis") => CacheBackend::Redis(RedisConfig {
+                url: env::var("REDIS_URL").expect("REDIS_URL must be set for the redis cache"),
+                key_prefix: env::var("REDIS_KEY_PREFIX")
+                    .unwrap_or_else(|_| "qn-gateway".to_string()),
+            }),
+            Ok(other) => panic!("unknown CACHE_BACKEND {}", other),
+        };
+
+        // Zero disables stale-while-revalidate.
+        let cache_stale_grace_ms: u64 = env::var("CACHE_STALE_GRACE_MS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0);
+
+        // Zero keeps every entry uncompressed.
+        let cache_compress_min_bytes: usize = env::var("CACHE_COMPRESS_MIN_BYTES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(16 * 1024);
+
+        // Requests from the config file, plus parameterless calls to the listed methods.
+        let mut warm_requests = file.warm_requests;
+        if let Ok(methods) = env::var("CACHE_WARM_METHODS") {
+            warm_requests.extend(
+                methods
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|method| !method.is_empty())
+                    .map(|method| json!({ "jsonrpc": "2.0", "id": 1, "method": method })),
+            );
+        }
+        let cache_warming = WarmingConfig {
+            templates: warm_requests,
+            lead: Duration::from_millis(
+                env::var("CACHE_WARM_LEAD_MS")
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(300),
+            ),
+            // Zero disables learning the hot set from cache hits.
+            learn_top: env::var("CACHE_WARM_LEARN_TOP")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(0),
+        };
+
+        let cache_snapshot_file = env::var("CACHE_SNAPSHOT_FILE").ok().map(PathBuf::from);
+
+        // Zero disables the background sweeper.
+        let cache_sweep_interval_ms: u64 = env::var("CACHE_SWEEP_INTERVAL_MS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(1_000);
+
         let request_timeout_ms: u64 = env::var("REQUEST_TIMEOUT_MS")
             .ok()
             .and_then(|value| value.parse().ok())
             .unwrap_or(8_000);
 
+        let max_response_bytes: u64 = env::var("MAX_RESPONSE_BYTES")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(64 * 1024 * 1024);
+
         let retry_attempts: usize = env::var("RETRY_ATTEMPTS")
             .ok()
             .and_then(|value| value.parse().ok())
             .unwrap_or(3);
 
+        let retry_base_backoff_ms: u64 = env::var("RETRY_BASE_BACKOFF_MS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(100);
+
+        let retry_max_backoff_ms: u64 = env::var("RETRY_MAX_BACKOFF_MS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(2_000);
+
+        // Retries share one budget that never outlasts the overall request timeout.
+        let retry_deadline_ms: u64 = env::var("RETRY_DEADLINE_MS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(request_timeout_ms)
+            .min(request_timeout_ms);
+
         let bind_addr = env::var("BIND_ADDR").unwrap_or_else(|_| "0.0.0.0:8080".to_string());
 
+        // An empty token would let anyone in, so it counts as unset.
+        let admin_token = env::var("ADMIN_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty());
+
         Self {
-            quicknode_url,
+            upstream_pools,
+            routes: RouteTable::new(file.routes),
+            upstream_strategy,
+            circuit_breaker,
+            health_check_interval: (health_check_interval_ms > 0)
+                .then(|| Duration::from_millis(health_check_interval_ms)),
             quicknode_ws_url,
+            slot_invalidation,
+            account_invalidation,
+            account_watch_limit,
             privacy_mode,
-            cache_ttl: Duration::from_secs(cache_ttl_seconds),
+            method_policy: MethodPolicy::for_mode(privacy_mode).with_overrides(file.method_policy),
+            cache_ttls,
+            negative_cache,
+            default_commitment,
+            cache_limits: CacheLimits {
+                max_entries: cache_max_entries,
+                max_bytes: cache_max_bytes,
+            },
+            cache_backend,
+            cache_stale_grace: Duration::from_millis(cache_stale_grace_ms),
+            cache_compress_min_bytes: (cache_compress_min_bytes > 0)
+                .then_some(cache_compress_min_bytes),
+            cache_warming,
+            cache_snapshot_file,
+            cache_sweep_interval: (cache_sweep_interval_ms > 0)
+                .then(|| Duration::from_millis(cache_sweep_interval_ms)),
             request_timeout: Duration::from_millis(request_timeout_ms),
-            retry_attempts,
+            max_response_bytes,
+            retry_policies: RetryPolicies::solana_defaults(retry_attempts)
+                .with_overrides(file.retry_policies),
+            retry_backoff: BackoffConfig {
+                base: Duration::from_millis(retry_base_backoff_ms),
+                max: Duration::from_millis(retry_max_backoff_ms),
+                deadline: Duration::from_millis(retry_deadline_ms),
+            },
             bind_addr,
+            admin_token,
         }
     }
 }
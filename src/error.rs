@@ -0,0 +1,178 @@
+//! Gateway error type and its JSON-RPC 2.0 error representation.
+
+use serde_json::{json, Value};
+use std::fmt;
+
+/// Invalid JSON was received by the gateway.
+pub const PARSE_ERROR: i64 = -32700;
+/// The payload is not a valid JSON-RPC request object.
+pub const INVALID_REQUEST: i64 = -32600;
+/// Unexpected failure inside the gateway itself.
+pub const INTERNAL_ERROR: i64 = -32603;
+
+// Gateway-specific codes live in the implementation-defined server error range
+// (-32000..=-32099), above the codes Solana nodes use for their own errors.
+pub const UPSTREAM_TIMEOUT: i64 = -32050;
+pub const UPSTREAM_CLIENT_ERROR: i64 = -32051;
+pub const UPSTREAM_SERVER_ERROR: i64 = -32052;
+pub const UPSTREAM_UNAVAILABLE: i64 = -32053;
+pub const UPSTREAM_INVALID_RESPONSE: i64 = -32054;
+pub const POLICY_REJECTED: i64 = -32055;
+pub const UPSTREAM_RESPONSE_TOO_LARGE: i64 = -32056;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GatewayError {
+    /// The request body could not be parsed as JSON.
+    Parse(String),
+    /// The request is not a well-formed JSON-RPC call.
+    InvalidRequest(String),
+    /// The gateway's policy refuses to forward the call.
+    PolicyRejected(String),
+    /// Upstream did not answer within the request timeout.
+    UpstreamTimeout,
+    /// Upstream answered with an HTTP 4xx status.
+    UpstreamClientError(u16),
+    /// Upstream answered with an HTTP 5xx status.
+    UpstreamServerError(u16),
+    /// Upstream could not be reached at all.
+    UpstreamUnavailable(String),
+    /// Upstream answered with a body the gateway cannot use.
+    InvalidUpstreamResponse(String),
+    /// Upstream's body is larger than the configured response size limit.
+    ResponseTooLarge(u64),
+    /// Failure inside the gateway that is not the caller's or upstream's fault.
+    Internal(String),
+}
+
+impl GatewayError {
+    pub fn code(&self) -> i64 {
+        match self {
+            GatewayError::Parse(_) => PARSE_ERROR,
+            GatewayError::InvalidRequest(_) => INVALID_REQUEST,
+            GatewayError::PolicyRejected(_) => POLICY_REJECTED,
+            GatewayError::UpstreamTimeout => UPSTREAM_TIMEOUT,
+            GatewayError::UpstreamClientError(_) => UPSTREAM_CLIENT_ERROR,
+            GatewayError::UpstreamServerError(_) => UPSTREAM_SERVER_ERROR,
+            GatewayError::UpstreamUnavailable(_) => UPSTREAM_UNAVAILABLE,
+            GatewayError::InvalidUpstreamResponse(_) => UPSTREAM_INVALID_RESPONSE,
+            GatewayError::ResponseTooLarge(_) => UPSTREAM_RESPONSE_TOO_LARGE,
+            GatewayError::Internal(_) => INTERNAL_ERROR,
+        }
+    }
+
+    fn data(&self) -> Option<Value> {
+        match self {
+            GatewayError::UpstreamClientError(status)
+            | GatewayError::UpstreamServerError(status) => {
+                Some(json!({ "upstream_status": status }))
+            }
+            GatewayError::ResponseTooLarge(limit) => Some(json!({ "max_response_bytes": limit })),
+            GatewayError::Parse(detail)
+            | GatewayError::InvalidRequest(detail)
+            | GatewayError::PolicyRejected(detail)
+            | GatewayError::UpstreamUnavailable(detail)
+            | GatewayError::InvalidUpstreamResponse(detail)
+            | GatewayError::Internal(detail) => Some(json!({ "detail": detail })),
+            GatewayError::UpstreamTimeout => None,
+        }
+    }
+
+    /// Builds a JSON-RPC 2.0 error response echoing the caller's id.
+    pub fn to_rpc_response(&self, id: Value) -> Value {
+        let mut error = json!({
+            "code": self.code(),
+            "message": self.to_string(),
+        });
+        if let Some(data) = self.data() {
+            error["data"] = data;
+        }
+
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": error,
+        })
+    }
+}
+
+impl fmt::Display for GatewayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GatewayError::Parse(_) => write!(f, "parse error"),
+            GatewayError::InvalidRequest(_) => write!(f, "invalid request"),
+            GatewayError::PolicyRejected(_) => write!(f, "rejected by gateway policy"),
+            GatewayError::UpstreamTimeout => write!(f, "upstream timeout"),
+            GatewayError::UpstreamClientError(status) => {
+                write!(f, "upstream client error: {}", status)
+            }
+            GatewayError::UpstreamServerError(status) => {
+                write!(f, "upstream server error: {}", status)
+            }
+            GatewayError::UpstreamUnavailable(_) => write!(f, "upstream unavailable"),
+            GatewayError::InvalidUpstreamResponse(_) => write!(f, "invalid upstream response"),
+            GatewayError::ResponseTooLarge(_) => write!(f, "upstream response too large"),
+            GatewayError::Internal(_) => write!(f, "internal gateway error"),
+        }
+    }
+}
+
+impl std::error::Error for GatewayError {}
+
+impl From<reqwest::Error> for GatewayError {
+    fn from(err: reqwest::Error) -> Self {
+        // Drop the URL so upstream credentials never end up in client-facing errors.
+        let err = err.without_url();
+        if err.is_timeout() {
+            GatewayError::UpstreamTimeout
+        } else if err.is_decode() {
+            GatewayError::InvalidUpstreamResponse(err.to_string())
+        } else {
+            GatewayError::UpstreamUnavailable(err.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_response_echoes_id() {
+        let response = GatewayError::UpstreamTimeout.to_rpc_response(json!("abc"));
+
+        assert_eq!(response["jsonrpc"], "2.0");
+        assert_eq!(response["id"], "abc");
+        assert_eq!(response["error"]["code"], UPSTREAM_TIMEOUT);
+        assert!(response["error"].get("data").is_none());
+    }
+
+    #[test]
+    fn test_upstream_status_is_reported_in_data() {
+        let response = GatewayError::UpstreamServerError(503).to_rpc_response(json!(1));
+
+        assert_eq!(response["error"]["code"], UPSTREAM_SERVER_ERROR);
+        assert_eq!(response["error"]["message"], "upstream server error: 503");
+        assert_eq!(response["error"]["data"]["upstream_status"], 503);
+    }
+
+    #[test]
+    fn test_error_codes_are_distinct() {
+        let errors = [
+            GatewayError::Parse(String::new()),
+            GatewayError::InvalidRequest(String::new()),
+            GatewayError::PolicyRejected(String::new()),
+            GatewayError::UpstreamTimeout,
+            GatewayError::UpstreamClientError(400),
+            GatewayError::UpstreamServerError(500),
+            GatewayError::UpstreamUnavailable(String::new()),
+            GatewayError::InvalidUpstreamResponse(String::new()),
+            GatewayError::ResponseTooLarge(0),
+            GatewayError::Internal(String::new()),
+        ];
+        let mut codes: Vec<i64> = errors.iter().map(GatewayError::code).collect();
+        codes.sort_unstable();
+        codes.dedup();
+
+        assert_eq!(codes.len(), errors.len());
+    }
+}
@@ -0,0 +1,64 @@
+//! Active upstream health checks and circuit state reporting.
+
+use crate::circuit_breaker::CircuitEvent;
+use crate::log_events::LogEvent;
+use crate::server::AppState;
+use crate::upstream::Upstream;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::time::{interval, MissedTickBehavior};
+
+/// Probes every upstream with `getHealth` on a fixed schedule.
+pub async fn run_health_checks(state: AppState, every: Duration) {
+    let mut ticker = interval(every);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        ticker.tick().await;
+        for upstream in state.upstreams.iter().flat_map(|(_, pool)| pool.iter()) {
+            let healthy = probe(&state.client, upstream).await;
+            let event = if healthy {
+                upstream.breaker.record_success()
+            } else {
+                upstream.breaker.record_failure()
+            };
+            tracing::debug!(upstream = %upstream.name, healthy, "health probe");
+            if let Some(event) = event {
+                record_circuit_event(&state, upstream, event).await;
+            }
+        }
+    }
+}
+
+async fn probe(client: &reqwest::Client, upstream: &Upstream) -> bool {
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": "getHealth" });
+    let Ok(response) = client.post(&upstream.url).json(&request).send().await else {
+        return false;
+    };
+    if !response.status().is_success() {
+        return false;
+    }
+
+    // A healthy node answers `"ok"`; lagging nodes answer with an error object.
+    response
+        .json::<Value>()
+        .await
+        .map(|body| body.get("result").and_then(Value::as_str) == Some("ok"))
+        .unwrap_or(false)
+}
+
+pub async fn record_circuit_event(state: &AppState, upstream: &Upstream, event: CircuitEvent) {
+    let log_event = match event {
+        CircuitEvent::Down => {
+            tracing::warn!(upstream = %upstream.name, "upstream circuit opened");
+            LogEvent::new("WARN", "UPSTREAM_DOWN")
+                .with_note(format!("{} circuit open", upstream.name))
+        }
+        CircuitEvent::Up => {
+            tracing::info!(upstream = %upstream.name, "upstream circuit closed");
+            LogEvent::new("INFO", "UPSTREAM_UP")
+                .with_note(format!("{} circuit closed", upstream.name))
+        }
+    };
+    state.log_state.record(log_event).await;
+}
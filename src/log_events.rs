@@ -3,6 +3,7 @@
 use chrono::Utc;
 use serde::Serialize;
 use std::collections::VecDeque;
+use std::time::Duration;
 use tokio::sync::{broadcast, RwLock};
 
 #[derive(Clone, Debug, Serialize)]
@@ -15,8 +16,12 @@ pub struct LogEvent {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub method: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub latency_ms: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub note: Option<String>,
 }
 
@@ -28,7 +33,9 @@ impl LogEvent {
             event: event.to_string(),
             request_hash: None,
             method: None,
+            pool: None,
             latency_ms: None,
+            ttl_ms: None,
             note: None,
         }
     }
@@ -43,11 +50,21 @@ impl LogEvent {
         self
     }
 
+    pub fn with_pool(mut self, pool: String) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
     pub fn with_latency(mut self, latency_ms: u64) -> Self {
         self.latency_ms = Some(latency_ms);
         self
     }
 
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl_ms = Some(ttl.as_millis() as u64);
+        self
+    }
+
     pub fn with_note(mut self, note: impl Into<String>) -> Self {
         self.note = Some(note.into());
         self
@@ -1,14 +1,32 @@
 //! Service entry point and runtime setup.
 
+mod account_watcher;
+mod admin;
 mod cache;
+mod cache_snapshot;
+mod cache_ttl;
+mod cache_warmer;
+mod circuit_breaker;
+mod commitment;
 mod config;
 mod dashboard;
+mod error;
+mod health;
 mod log_events;
+mod method_pattern;
+mod method_policy;
 mod metrics;
 mod normalize;
 mod privacy_mode;
 mod proxy;
+mod refresh_backoff;
+mod retry;
+mod routing;
 mod server;
+mod singleflight;
+mod slot_watcher;
+mod streaming;
+mod upstream;
 
 use crate::config::Config;
 use crate::metrics::Metrics;
@@ -35,7 +53,7 @@ async fn main() {
         .parse::<SocketAddr>()
         .expect("invalid bind address");
 
-    let app: Router = build_router(Arc::new(config), Arc::new(metrics));
+    let app: Router = build_router(Arc::new(config), Arc::new(metrics)).await;
 
     tracing::info!(%bind_addr, "starting server");
 
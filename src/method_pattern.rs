@@ -0,0 +1,65 @@
+//! RPC method name patterns with `*` wildcards.
+
+use serde::{Deserialize, Deserializer};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MethodPattern(String);
+
+impl MethodPattern {
+    pub fn new(pattern: impl Into<String>) -> Self {
+        Self(pattern.into())
+    }
+
+    /// Matches the whole method name; `*` stands for any run of characters.
+    pub fn matches(&self, method: &str) -> bool {
+        let mut parts = self.0.split('*');
+        let first = parts.next().unwrap_or("");
+        let Some(mut rest) = method.strip_prefix(first) else {
+            return false;
+        };
+
+        let mut parts: Vec<&str> = parts.collect();
+        let Some(last) = parts.pop() else {
+            // No wildcard at all: the prefix must be the whole name.
+            return rest.is_empty();
+        };
+
+        for part in parts {
+            match rest.find(part) {
+                Some(position) => rest = &rest[position + part.len()..],
+                None => return false,
+            }
+        }
+        rest.ends_with(last)
+    }
+}
+
+impl<'de> Deserialize<'de> for MethodPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(MethodPattern::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_pattern() {
+        let pattern = MethodPattern::new("getBlock");
+
+        assert!(pattern.matches("getBlock"));
+        assert!(!pattern.matches("getBlockHeight"));
+        assert!(!pattern.matches("getBloc"));
+    }
+
+    #[test]
+    fn test_wildcard_patterns() {
+        assert!(MethodPattern::new("*").matches("getSlot"));
+        assert!(MethodPattern::new("getBlock*").matches("getBlockHeight"));
+        assert!(MethodPattern::new("*Subscribe").matches("slotSubscribe"));
+        assert!(MethodPattern::new("get*Account*").matches("getProgramAccounts"));
+        assert!(!MethodPattern::new("get*Account*").matches("getBalance"));
+        assert!(!MethodPattern::new("*Subscribe").matches("slotUnsubscribed"));
+    }
+}
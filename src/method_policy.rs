@@ -0,0 +1,97 @@
+//! Which RPC methods the gateway forwards: an optional allowlist and a
+//! denylist of method patterns, with defaults per privacy mode.
+
+use crate::method_pattern::MethodPattern;
+use crate::privacy_mode::PrivacyMode;
+use serde::Deserialize;
+
+/// Lists from the config file; a list that is set replaces the mode's default.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct MethodPolicyOverride {
+    pub allow: Option<Vec<MethodPattern>>,
+    pub deny: Option<Vec<MethodPattern>>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MethodPolicy {
+    /// Empty allows every method that is not denied.
+    allow: Vec<MethodPattern>,
+    deny: Vec<MethodPattern>,
+}
+
+impl MethodPolicy {
+    /// Built-in lists: strict refuses account scans and airdrops, balanced only
+    /// airdrops, and dev forwards everything.
+    pub fn for_mode(mode: PrivacyMode) -> Self {
+        let deny: &[&str] = match mode {
+            PrivacyMode::Strict => &["getProgramAccounts", "getLargestAccounts", "requestAirdrop"],
+            PrivacyMode::Balanced => &["requestAirdrop"],
+            PrivacyMode::Dev => &[],
+        };
+        Self {
+            allow: Vec::new(),
+            deny: deny
+                .iter()
+                .map(|method| MethodPattern::new(*method))
+                .collect(),
+        }
+    }
+
+    pub fn with_overrides(mut self, overrides: MethodPolicyOverride) -> Self {
+        if let Some(allow) = overrides.allow {
+            self.allow = allow;
+        }
+        if let Some(deny) = overrides.deny {
+            self.deny = deny;
+        }
+        self
+    }
+
+    /// Why `method` must not be forwarded, or `None` when it may be. A denied
+    /// method stays denied even when it is also allowed.
+    pub fn rejection(&self, method: &str) -> Option<String> {
+        if self.deny.iter().any(|pattern| pattern.matches(method)) {
+            return Some(format!("method {} is denied", method));
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|pattern| pattern.matches(method)) {
+            return Some(format!("method {} is not allowed", method));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_defaults() {
+        let strict = MethodPolicy::for_mode(PrivacyMode::Strict);
+        assert!(strict.rejection("getProgramAccounts").is_some());
+        assert!(strict.rejection("getBalance").is_none());
+        assert!(MethodPolicy::for_mode(PrivacyMode::Dev)
+            .rejection("requestAirdrop")
+            .is_none());
+    }
+
+    #[test]
+    fn test_overrides_replace_lists_and_deny_wins() {
+        let overrides: MethodPolicyOverride = serde_json::from_str(
+            r#"{"allow": ["get*", "sendTransaction"], "deny": ["getBlock*"]}"#,
+        )
+        .unwrap();
+        let policy = MethodPolicy::for_mode(PrivacyMode::Strict).with_overrides(overrides);
+
+        assert!(policy.rejection("getProgramAccounts").is_none());
+        assert!(policy.rejection("sendTransaction").is_none());
+        assert_eq!(
+            policy.rejection("getBlockHeight").as_deref(),
+            Some("method getBlockHeight is denied")
+        );
+        assert_eq!(
+            policy.rejection("requestAirdrop").as_deref(),
+            Some("method requestAirdrop is not allowed")
+        );
+    }
+}
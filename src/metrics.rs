@@ -1,8 +1,9 @@
 //! Lightweight in-process metrics tracking.
 
+use crate::cache::CacheStats;
 use serde_json::json;
 use serde_json::Value;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::sync::atomic::{AtomicU64, Ordering};
 use tokio::sync::RwLock;
 
@@ -10,7 +11,11 @@ pub struct Metrics {
     requests_total: AtomicU64,
     cache_hits: AtomicU64,
     cache_misses: AtomicU64,
+    stale_hits: AtomicU64,
+    negative_hits: AtomicU64,
+    coalesced: AtomicU64,
     unique_hashes: RwLock<HashSet<String>>,
+    pool_requests: RwLock<BTreeMap<String, u64>>,
 }
 
 impl Metrics {
@@ -19,7 +24,11 @@ impl Metrics {
             requests_total: AtomicU64::new(0),
             cache_hits: AtomicU64::new(0),
             cache_misses: AtomicU64::new(0),
+            stale_hits: AtomicU64::new(0),
+            negative_hits: AtomicU64::new(0),
+            coalesced: AtomicU64::new(0),
             unique_hashes: RwLock::new(HashSet::new()),
+            pool_requests: RwLock::new(BTreeMap::new()),
         }
     }
 
@@ -38,15 +47,41 @@ impl Metrics {
         self.cache_misses.fetch_add(1, Ordering::Relaxed);
     }
 
-    pub async fn snapshot(&self) -> Value {
+    /// A hit served past its TTL; also counted in `cache_hits`.
+    pub fn record_stale_hit(&self) {
+        self.stale_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A hit on a cached error or `null` result; also counted in `cache_hits`.
+    pub fn record_negative_hit(&self) {
+        self.negative_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_coalesced(&self) {
+        self.coalesced.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub async fn record_pool_request(&self, pool: &str) {
+        // Upstream calls per routing pool.
+        let mut guard = self.pool_requests.write().await;
+        *guard.entry(pool.to_string()).or_insert(0) += 1;
+    }
+
+    pub async fn snapshot(&self, cache: CacheStats) -> Value {
         // Snapshot is intentionally lightweight for the dashboard polling.
         let unique_request_hashes = self.unique_hashes.read().await.len();
+        let pool_requests = self.pool_requests.read().await.clone();
 
         json!({
             "requests_total": self.requests_total.load(Ordering::Relaxed),
             "cache_hits": self.cache_hits.load(Ordering::Relaxed),
             "cache_misses": self.cache_misses.load(Ordering::Relaxed),
-            "unique_request_hashes": unique_request_hashes
+            "stale_hits": self.stale_hits.load(Ordering::Relaxed),
+            "negative_hits": self.negative_hits.load(Ordering::Relaxed),
+            "coalesced": self.coalesced.load(Ordering::Relaxed),
+            "unique_request_hashes": unique_request_hashes,
+            "pool_requests": pool_requests,
+            "cache": cache
         })
     }
 }
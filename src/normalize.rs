@@ -3,6 +3,11 @@
 use crate::privacy_mode::PrivacyMode;
 use serde_json::{Map, Value};
 
+/// Version of the request normalization behind cache keys. Bump it whenever a
+/// change gives some request a different hash, so snapshots taken by older
+/// builds are rejected instead of filling the cache with unreachable entries.
+pub const NORMALIZATION_VERSION: u32 = 2;
+
 pub fn normalize_rpc_request(value: Value) -> Value {
     match value {
         Value::Object(map) => {
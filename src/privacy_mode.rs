@@ -42,7 +42,12 @@ impl FromStr for PrivacyMode {
 pub fn is_cacheable_method(method: &str) -> bool {
     matches!(
         method,
-        "getAccountInfo" | "getBalance" | "getLatestBlockhash" | "getSlot" | "getBlock"
+        "getAccountInfo"
+            | "getBalance"
+            | "getLatestBlockhash"
+            | "getSlot"
+            | "getBlock"
+            | "getTransaction"
     )
 }
 
@@ -1,141 +1,647 @@
 //! Proxy logic for forwarding requests and applying privacy features.
 
+use crate::account_watcher::watched_account;
+use crate::cache_ttl::is_negative;
+use crate::commitment::{canonicalize_commitment, request_commitment, Commitment};
+use crate::config::Config;
+use crate::error::GatewayError;
+use crate::health::record_circuit_event;
 use crate::log_events::LogEvent;
 use crate::normalize::{normalize_for_mode, normalize_rpc_request};
+use crate::retry::{parse_retry_after, RetryOn, RetryPolicy};
 use crate::server::AppState;
+use crate::streaming::{ResponseStream, StreamOutcome};
+use crate::upstream::{Upstream, UpstreamPool};
 use axum::extract::ws::{Message as AxumMessage, WebSocket};
+use futures_util::future::join_all;
 use futures_util::{SinkExt, StreamExt};
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
 use serde_json::Value;
 use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
 use std::time::{Duration, Instant};
 use tokio::time::sleep;
 use tokio_tungstenite::connect_async;
 use tokio_tungstenite::tungstenite::Message as TungsteniteMessage;
 
-const BASE_BACKOFF_MS: u64 = 100;
+/// A single JSON-RPC call with the metadata needed for caching and logging.
+#[derive(Clone)]
+struct RpcCall {
+    payload: Value,
+    method: String,
+    id: Option<Value>,
+    request_hash: String,
+    pool: String,
+    /// Commitment the call is read at, the cluster default when it states none.
+    commitment: Commitment,
+    /// Cache lifetime of the response; `None` when the privacy mode never caches it.
+    ttl: Option<Duration>,
+}
 
-pub async fn handle_rpc_request(state: AppState, payload: Value) -> Result<Value, String> {
-    let start = Instant::now();
+impl RpcCall {
+    fn prepare(config: &Config, payload: Value) -> Result<Self, GatewayError> {
+        if !payload.is_object() {
+            return Err(GatewayError::InvalidRequest(
+                "request is not an object".to_string(),
+            ));
+        }
+        // Pull method early for routing, caching, and logging.
+        let method = payload
+            .get("method")
+            .and_then(Value::as_str)
+            .ok_or_else(|| GatewayError::InvalidRequest("missing method".to_string()))?
+            .to_string();
+        let id = payload.get("id").cloned();
 
-    // Pull method early for routing, caching, and logging.
-    let method = payload
-        .get("method")
-        .and_then(Value::as_str)
-        .unwrap_or("")
-        .to_string();
+        // Normalize for deterministic hashing, separate from outbound normalization.
+        // An omitted commitment hashes like the cluster default stated explicitly.
+        let canonical = canonicalize_commitment(payload.clone(), config.default_commitment);
+        let normalized_for_hash = normalize_for_mode(config.privacy_mode, canonical);
+        let request_hash = hash_value(&normalized_for_hash)?;
+        let pool = config.routes.pool_for(&method).to_string();
 
-    let mode = state.config.privacy_mode;
+        let commitment = request_commitment(&payload).unwrap_or(config.default_commitment);
+        let ttl = if config.privacy_mode.should_cache(&method) {
+            config.cache_ttls.for_call(&method, commitment)
+        } else {
+            None
+        };
 
-    // Normalize for deterministic hashing, separate from outbound normalization.
-    let normalized_for_hash = normalize_for_mode(mode, payload.clone());
-    let request_hash = hash_value(&normalized_for_hash)?;
+        Ok(Self {
+            payload,
+            method,
+            id,
+            request_hash,
+            pool,
+            commitment,
+            ttl,
+        })
+    }
+}
 
-    state.metrics.record_request(request_hash.clone()).await;
-    tracing::info!(method = %method, hash = %request_hash, "incoming request");
-    state
-        .log_state
-        .record(LogEvent::new("INFO", "REQ_IN").with_method(method.clone()))
+/// Request hash of `payload`, or `None` when the privacy mode never caches it.
+pub fn cache_key(config: &Config, payload: Value) -> Result<Option<String>, GatewayError> {
+    let call = RpcCall::prepare(config, payload)?;
+    Ok(call.ttl.map(|_| call.request_hash))
+}
+
+/// Fetches `payload` upstream and caches the answer as a client miss would,
+/// joining a call already in flight for it; returns how long it was cached.
+/// Methods the policy refuses are rejected without contacting upstream.
+pub async fn warm_cache(
+    state: &AppState,
+    mut payload: Value,
+) -> Result<Option<Duration>, GatewayError> {
+    // Without an id upstream would treat the call as a notification and not answer.
+    if let Value::Object(ref mut map) = payload {
+        map.insert("id".to_string(), Value::from(1));
+    }
+    let call = RpcCall::prepare(&state.config, payload)?;
+    if call.ttl.is_none() {
+        return Ok(None);
+    }
+    enforce_method_policy(state, &call).await?;
+    let (result, _) = state
+        .flights
+        .run(&call.request_hash, || {
+            forward_single(state.clone(), call.clone())
+        })
         .await;
+    Ok(response_ttl(state, &call, &result?))
+}
+
+/// Response body handed back to the HTTP layer.
+pub enum RpcResponse {
+    Json(Value),
+    /// Upstream's body relayed as bytes, for calls the privacy mode never caches.
+    Stream(ResponseStream),
+    /// Nothing to answer: every element of the batch was a notification.
+    Empty,
+}
+
+pub async fn handle_rpc_request(
+    state: AppState,
+    payload: Value,
+) -> Result<RpcResponse, GatewayError> {
+    match payload {
+        Value::Array(items) => handle_batch(state, items).await,
+        single => handle_single(state, single).await,
+    }
+}
+
+async fn handle_single(state: AppState, payload: Value) -> Result<RpcResponse, GatewayError> {
+    let start = Instant::now();
+    let call = RpcCall::prepare(&state.config, payload)?;
+
+    record_incoming(&state, &call).await;
+    enforce_method_policy(&state, &call).await?;
+
+    // Nothing to cache, so skip decoding and hand upstream's bytes straight back.
+    if call.ttl.is_none() {
+        return stream_single(state, call, start)
+            .await
+            .map(RpcResponse::Stream);
+    }
 
     // Cache lookup only for safe read methods.
-    if mode.should_cache(&method) {
-        if let Some(cached) = state.cache.get(&request_hash).await {
-            state.metrics.record_cache_hit();
-            tracing::info!(method = %method, hash = %request_hash, "cache hit");
-            state
-                .log_state
-                .record(
-                    LogEvent::new("INFO", "CACHE_HIT")
-                        .with_hash(request_hash.clone())
-                        .with_method(method.clone()),
-                )
-                .await;
-            return Ok(cached);
-        }
-
-        state.metrics.record_cache_miss();
-        tracing::info!(method = %method, hash = %request_hash, "cache miss");
-        state
-            .log_state
-            .record(
-                LogEvent::new("INFO", "CACHE_MISS")
-                    .with_hash(request_hash.clone())
-                    .with_method(method.clone()),
-            )
-            .await;
+    if let Some(cached) = lookup_cache(&state, &call).await {
+        return Ok(RpcResponse::Json(cached));
     }
 
-    // Normalize outbound request body when privacy mode allows.
-    let outbound_payload = if mode.should_normalize_outbound() {
-        normalize_rpc_request(payload)
+    // Identical cacheable reads already in flight share one upstream call.
+    let policy = state.config.retry_policies.for_method(&call.method);
+    let response = if policy.idempotent {
+        let (result, coalesced) = state
+            .flights
+            .run(&call.request_hash, || {
+                forward_single(state.clone(), call.clone())
+            })
+            .await;
+        if coalesced {
+            record_coalesced(&state, &call).await;
+            // The shared response carries the leader's id.
+            result.map(|response| with_id(response, call.id.clone()))
+        } else {
+            result
+        }
     } else {
-        payload
+        forward_single(state.clone(), call.clone()).await
+    }?;
+
+    let ttl = response_ttl(&state, &call, &response);
+    record_response_out(&state, &call, start.elapsed(), ttl, None).await;
+
+    Ok(RpcResponse::Json(response))
+}
+
+/// Forwards one call upstream and caches the response when allowed.
+async fn forward_single(state: AppState, call: RpcCall) -> Result<Value, GatewayError> {
+    let outbound_payload = outbound_payload(&state, &call);
+    record_forwarding(&state, &call).await;
+
+    // Forward upstream with bounded retries and backoff.
+    let policy = state.config.retry_policies.for_method(&call.method);
+    let pool = state.upstreams.get(&call.pool);
+    let limit = state.config.max_response_bytes;
+    let response = match send_with_retries(&state, pool, outbound_payload, policy, |resp| {
+        read_json(resp, limit)
+    })
+    .await
+    {
+        Ok(value) => value,
+        Err(err) => {
+            record_upstream_error(&state, &call, &err).await;
+            return Err(err);
+        }
+    };
+
+    store_response(&state, &call, &response).await;
+
+    Ok(response)
+}
+
+/// Forwards one uncacheable call and relays upstream's body without decoding it.
+async fn stream_single(
+    state: AppState,
+    call: RpcCall,
+    start: Instant,
+) -> Result<ResponseStream, GatewayError> {
+    let outbound_payload = outbound_payload(&state, &call);
+    record_forwarding(&state, &call).await;
+
+    // Retries cover everything up to the response headers; the body is relayed as it arrives.
+    let policy = state.config.retry_policies.for_method(&call.method);
+    let pool = state.upstreams.get(&call.pool);
+    let response = match send_with_retries(&state, pool, outbound_payload, policy, |resp| async {
+        Ok(resp)
+    })
+    .await
+    {
+        Ok(resp) => resp,
+        Err(err) => {
+            record_upstream_error(&state, &call, &err).await;
+            return Err(err);
+        }
+    };
+
+    // The outbound id is the caller's own, so the body needs no rewriting.
+    let limit = state.config.max_response_bytes;
+    Ok(ResponseStream::new(
+        response.bytes_stream(),
+        limit,
+        move |outcome| {
+            // Runs from poll or drop, so log events are recorded on a separate task.
+            tokio::spawn(async move {
+                match outcome {
+                    StreamOutcome::Complete(bytes) => {
+                        let note = format!("streamed {} bytes", bytes);
+                        record_response_out(&state, &call, start.elapsed(), None, Some(note)).await;
+                    }
+                    StreamOutcome::Failed(err) => {
+                        record_upstream_error(&state, &call, &err).await;
+                    }
+                    StreamOutcome::Abandoned(bytes) => {
+                        let note = format!("client disconnected after {} bytes", bytes);
+                        record_response_out(&state, &call, start.elapsed(), None, Some(note)).await;
+                    }
+                }
+            });
+        },
+    ))
+}
+
+/// Normalizes the outbound request body when the privacy mode allows.
+fn outbound_payload(state: &AppState, call: &RpcCall) -> Value {
+    if state.config.privacy_mode.should_normalize_outbound() {
+        normalize_rpc_request(call.payload.clone())
+    } else {
+        call.payload.clone()
+    }
+}
+
+async fn handle_batch(state: AppState, items: Vec<Value>) -> Result<RpcResponse, GatewayError> {
+    if items.is_empty() {
+        return Err(GatewayError::InvalidRequest("empty batch".to_string()));
+    }
+
+    let start = Instant::now();
+    let mut responses: Vec<Option<Value>> = vec![None; items.len()];
+    let mut calls: Vec<Option<RpcCall>> = Vec::with_capacity(items.len());
+    let mut pending: Vec<usize> = Vec::new();
+
+    // Resolve every element against the cache on its own; only misses go upstream.
+    for (index, item) in items.into_iter().enumerate() {
+        if !item.is_object() {
+            let err = GatewayError::InvalidRequest("batch element is not an object".to_string());
+            responses[index] = Some(err.to_rpc_response(Value::Null));
+            calls.push(None);
+            continue;
+        }
+
+        let id = item.get("id").cloned().unwrap_or(Value::Null);
+        let call = match RpcCall::prepare(&state.config, item) {
+            Ok(call) => call,
+            Err(err) => {
+                responses[index] = Some(err.to_rpc_response(id));
+                calls.push(None);
+                continue;
+            }
+        };
+        record_incoming(&state, &call).await;
+
+        // A refused element is answered here; the rest of the batch still goes ahead.
+        if let Err(err) = enforce_method_policy(&state, &call).await {
+            responses[index] = Some(err.to_rpc_response(Value::Null));
+        } else if let Some(cached) = lookup_cache(&state, &call).await {
+            responses[index] = Some(cached);
+        } else {
+            pending.push(index);
+        }
+        calls.push(Some(call));
+    }
+
+    if !pending.is_empty() {
+        // Misses are grouped by routing pool; each group is one upstream batch.
+        let mut groups: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for &index in &pending {
+            let call = calls[index].as_ref().expect("pending call");
+            groups.entry(call.pool.as_str()).or_default().push(index);
+        }
+
+        let forwarded = join_all(
+            groups
+                .into_values()
+                .map(|indices| forward_batch(&state, &calls, indices, start)),
+        )
+        .await;
+        for (index, response) in forwarded.into_iter().flatten() {
+            responses[index] = Some(response);
+        }
+    }
+
+    // Restore the original order and ids; notifications (no id) get no response.
+    let body: Vec<Value> = responses
+        .into_iter()
+        .zip(calls.iter())
+        .filter_map(|(response, call)| match call {
+            Some(call) if call.id.is_none() => None,
+            Some(call) => response.map(|value| with_id(value, call.id.clone())),
+            None => response,
+        })
+        .collect();
+
+    // JSON-RPC 2.0: a batch of only notifications gets no response at all.
+    if body.is_empty() {
+        return Ok(RpcResponse::Empty);
+    }
+    Ok(RpcResponse::Json(Value::Array(body)))
+}
+
+/// Forwards the batch elements at `indices` (all routed to one pool) as a single
+/// upstream batch and returns their responses.
+async fn forward_batch(
+    state: &AppState,
+    calls: &[Option<RpcCall>],
+    indices: Vec<usize>,
+    start: Instant,
+) -> Vec<(usize, Value)> {
+    let pending: Vec<(usize, &RpcCall)> = indices
+        .into_iter()
+        .map(|index| (index, calls[index].as_ref().expect("pending call")))
+        .collect();
+    let pool = state.upstreams.get(&pending[0].1.pool);
+
+    // Outbound ids are batch positions so client-chosen ids never leave the gateway.
+    let mut outbound = Vec::with_capacity(pending.len());
+    for (position, (_, call)) in pending.iter().enumerate() {
+        let mut payload = call.payload.clone();
+        if let Value::Object(ref mut map) = payload {
+            map.insert("id".to_string(), Value::from(position));
+        }
+        if state.config.privacy_mode.should_normalize_outbound() {
+            payload = normalize_rpc_request(payload);
+        }
+        outbound.push(payload);
+        record_forwarding(state, call).await;
+    }
+
+    // One upstream request carries every miss, so it follows the strictest policy.
+    let policy = pending
+        .iter()
+        .map(|(_, call)| state.config.retry_policies.for_method(&call.method).clone())
+        .reduce(|combined, policy| combined.combine(&policy))
+        .expect("pending calls");
+    let limit = state.config.max_response_bytes;
+    let upstream = send_with_retries(state, pool, Value::Array(outbound), &policy, |resp| {
+        read_json(resp, limit)
+    })
+    .await
+    .and_then(|value| match value {
+        Value::Array(items) => Ok(items
+            .into_iter()
+            .filter_map(|item| {
+                let position = item.get("id").and_then(Value::as_u64)? as usize;
+                Some((position, item))
+            })
+            .collect::<HashMap<usize, Value>>()),
+        _ => Err(GatewayError::InvalidUpstreamResponse(
+            "expected an array for a batch request".to_string(),
+        )),
+    });
+
+    // A failed upstream batch fails only the forwarded elements; cache hits still stand.
+    let mut by_position = match upstream {
+        Ok(by_position) => by_position,
+        Err(err) => {
+            let mut responses = Vec::with_capacity(pending.len());
+            for (index, call) in pending {
+                record_upstream_error(state, call, &err).await;
+                responses.push((index, err.to_rpc_response(Value::Null)));
+            }
+            return responses;
+        }
     };
 
+    let elapsed = start.elapsed();
+    let mut responses = Vec::with_capacity(pending.len());
+    for (position, (index, call)) in pending.into_iter().enumerate() {
+        match by_position.remove(&position) {
+            Some(response) => {
+                let response = with_id(response, call.id.clone());
+                let ttl = store_response(state, call, &response).await;
+                record_response_out(state, call, elapsed, ttl, None).await;
+                responses.push((index, response));
+            }
+            None => {
+                let err = GatewayError::InvalidUpstreamResponse(
+                    "missing response for batch element".to_string(),
+                );
+                record_upstream_error(state, call, &err).await;
+                responses.push((index, err.to_rpc_response(Value::Null)));
+            }
+        }
+    }
+    responses
+}
+
+async fn record_incoming(state: &AppState, call: &RpcCall) {
+    state
+        .metrics
+        .record_request(call.request_hash.clone())
+        .await;
+    tracing::info!(method = %call.method, hash = %call.request_hash, "incoming request");
+    state
+        .log_state
+        .record(LogEvent::new("INFO", "REQ_IN").with_method(call.method.clone()))
+        .await;
+}
+
+/// Refuses a call whose method the policy does not allow, before anything
+/// about it reaches upstream.
+async fn enforce_method_policy(state: &AppState, call: &RpcCall) -> Result<(), GatewayError> {
+    let Some(reason) = state.config.method_policy.rejection(&call.method) else {
+        return Ok(());
+    };
+    tracing::warn!(method = %call.method, hash = %call.request_hash, "method rejected by policy");
     state
         .log_state
         .record(
-            LogEvent::new("INFO", "NORMALIZED")
-                .with_hash(request_hash.clone())
-                .with_method(method.clone()),
+            LogEvent::new("WARN", "POLICY_DENY")
+                .with_hash(call.request_hash.clone())
+                .with_method(call.method.clone())
+                .with_note(reason.clone()),
         )
         .await;
+    Err(GatewayError::PolicyRejected(reason))
+}
 
-    tracing::info!(method = %method, hash = %request_hash, "forwarding request");
+async fn lookup_cache(state: &AppState, call: &RpcCall) -> Option<Value> {
+    let ttl = call.ttl?;
+
+    if let Some(hit) = state.cache.lookup(&call.request_hash).await {
+        state.metrics.record_cache_hit();
+        // Only entries stored under the negative TTL count as negative hits.
+        if state.config.negative_cache.is_enabled() && is_negative(&hit.value) {
+            state.metrics.record_negative_hit();
+        }
+        if let Some(warmer) = &state.warmer {
+            warmer.record_hit(&call.request_hash, &call.payload);
+        }
+        if let (Some(watcher), Some(account)) = (
+            &state.account_watcher,
+            watched_account(&call.method, &call.payload),
+        ) {
+            watcher.touch(account, call.commitment);
+        }
+        tracing::info!(method = %call.method, hash = %call.request_hash, "cache hit");
+        let mut event = LogEvent::new("INFO", "CACHE_HIT")
+            .with_hash(call.request_hash.clone())
+            .with_method(call.method.clone());
+        if let Some(stale_for) = hit.stale_for {
+            // Past its TTL but within the grace window: answer now, refresh once behind it.
+            state.metrics.record_stale_hit();
+            let note = if state.refresh_backoff.is_backing_off(&call.request_hash) {
+                format!("stale {}ms, refresh failed", stale_for.as_millis())
+            } else if state.flights.spawn(&call.request_hash, || {
+                refresh_stale(state.clone(), call.clone(), stale_for)
+            }) {
+                format!("stale {}ms, refreshing", stale_for.as_millis())
+            } else {
+                format!("stale {}ms, refresh in flight", stale_for.as_millis())
+            };
+            event = event.with_note(note);
+        }
+        state.log_state.record(event).await;
+        // Entries are stored without an id; answer with the current caller's.
+        return Some(with_id(hit.value, call.id.clone()));
+    }
+
+    state.metrics.record_cache_miss();
+    tracing::info!(method = %call.method, hash = %call.request_hash, "cache miss");
     state
         .log_state
         .record(
-            LogEvent::new("INFO", "FORWARDED")
-                .with_hash(request_hash.clone())
-                .with_method(method.clone()),
+            LogEvent::new("INFO", "CACHE_MISS")
+                .with_hash(call.request_hash.clone())
+                .with_method(call.method.clone())
+                .with_ttl(ttl),
         )
         .await;
+    None
+}
 
-    // Forward upstream with bounded retries and backoff.
-    let response = match send_with_retries(
-        &state.client,
-        &state.config.quicknode_url,
-        outbound_payload,
-        state.config.retry_attempts,
-    )
-    .await
-    {
-        Ok(value) => value,
-        Err(err) => {
-            state
-                .log_state
-                .record(
-                    LogEvent::new("ERROR", "ERR")
-                        .with_hash(request_hash.clone())
-                        .with_method(method.clone())
-                        .with_note(err.clone()),
-                )
-                .await;
-            return Err(err);
-        }
+/// Refreshes an entry that has been stale for `stale_for`. When upstream fails
+/// or the answer cannot be cached, no further refresh starts for the rest of
+/// the grace window; later hits keep the stale answer until it runs out.
+async fn refresh_stale(
+    state: AppState,
+    call: RpcCall,
+    stale_for: Duration,
+) -> Result<Value, GatewayError> {
+    let grace_ends = Instant::now() + state.config.cache_stale_grace.saturating_sub(stale_for);
+    let result = forward_single(state.clone(), call.clone()).await;
+    let stored = match &result {
+        Ok(response) => response_ttl(&state, &call, response).is_some(),
+        Err(_) => false,
     };
-
-    // Populate cache on successful responses only.
-    if mode.should_cache(&method) {
+    if !stored {
         state
-            .cache
-            .insert(request_hash.clone(), response.clone())
-            .await;
+            .refresh_backoff
+            .record_failure(&call.request_hash, grace_ends);
     }
+    result
+}
 
-    let elapsed = start.elapsed();
-    tracing::info!(method = %method, hash = %request_hash, elapsed_ms = elapsed.as_millis(), "response completed");
+async fn record_coalesced(state: &AppState, call: &RpcCall) {
+    state.metrics.record_coalesced();
+    tracing::info!(method = %call.method, hash = %call.request_hash, "coalesced with in-flight request");
     state
         .log_state
         .record(
-            LogEvent::new("INFO", "RESP_OUT")
-                .with_hash(request_hash)
-                .with_method(method.clone())
-                .with_latency(elapsed.as_millis() as u64),
+            LogEvent::new("INFO", "COALESCED")
+                .with_hash(call.request_hash.clone())
+                .with_method(call.method.clone()),
         )
         .await;
+}
 
-    Ok(response)
+async fn record_forwarding(state: &AppState, call: &RpcCall) {
+    state
+        .log_state
+        .record(
+            LogEvent::new("INFO", "NORMALIZED")
+                .with_hash(call.request_hash.clone())
+                .with_method(call.method.clone()),
+        )
+        .await;
+
+    tracing::info!(method = %call.method, hash = %call.request_hash, pool = %call.pool, "forwarding request");
+    state.metrics.record_pool_request(&call.pool).await;
+    state
+        .log_state
+        .record(
+            LogEvent::new("INFO", "FORWARDED")
+                .with_hash(call.request_hash.clone())
+                .with_method(call.method.clone())
+                .with_pool(call.pool.clone()),
+        )
+        .await;
+}
+
+async fn record_upstream_error(state: &AppState, call: &RpcCall, err: &GatewayError) {
+    state
+        .log_state
+        .record(
+            LogEvent::new("ERROR", "ERR")
+                .with_hash(call.request_hash.clone())
+                .with_method(call.method.clone())
+                .with_pool(call.pool.clone())
+                .with_note(err.to_string()),
+        )
+        .await;
+}
+
+/// How long `response` may be cached: the call's TTL, the shorter negative TTL
+/// for errors and `null` results, or `None` for errors that are not cached.
+fn response_ttl(state: &AppState, call: &RpcCall, response: &Value) -> Option<Duration> {
+    state
+        .config
+        .negative_cache
+        .response_ttl(&call.method, response, call.ttl?)
+}
+
+/// Caches successful responses; returns the TTL when the response was stored.
+async fn store_response(state: &AppState, call: &RpcCall, response: &Value) -> Option<Duration> {
+    let ttl = response_ttl(state, call, response)?;
+
+    // Client-chosen ids never persist in the cache.
+    let mut stored = response.clone();
+    if let Value::Object(ref mut map) = stored {
+        map.remove("id");
+    }
+    state
+        .cache
+        .insert(call.request_hash.clone(), &call.method, stored, ttl)
+        .await;
+    if let (Some(watcher), Some(account)) = (
+        &state.account_watcher,
+        watched_account(&call.method, &call.payload),
+    ) {
+        watcher.watch(account, call.commitment, &call.request_hash);
+    }
+    Some(ttl)
+}
+
+async fn record_response_out(
+    state: &AppState,
+    call: &RpcCall,
+    elapsed: Duration,
+    ttl: Option<Duration>,
+    note: Option<String>,
+) {
+    tracing::info!(method = %call.method, hash = %call.request_hash, elapsed_ms = elapsed.as_millis(), "response completed");
+    let mut event = LogEvent::new("INFO", "RESP_OUT")
+        .with_hash(call.request_hash.clone())
+        .with_method(call.method.clone())
+        .with_pool(call.pool.clone())
+        .with_latency(elapsed.as_millis() as u64);
+    if let Some(ttl) = ttl {
+        event = event.with_ttl(ttl);
+    }
+    if let Some(note) = note {
+        event = event.with_note(note);
+    }
+    state.log_state.record(event).await;
+}
+
+/// Replaces the `id` of a JSON-RPC response object.
+fn with_id(mut response: Value, id: Option<Value>) -> Value {
+    if let Value::Object(ref mut map) = response {
+        map.insert("id".to_string(), id.unwrap_or(Value::Null));
+    }
+    response
 }
 
 pub async fn handle_ws_proxy(state: AppState, socket: WebSocket) {
@@ -226,46 +732,961 @@ fn map_from_upstream(message: TungsteniteMessage) -> Option<AxumMessage> {
     }
 }
 
-async fn send_with_retries(
-    client: &reqwest::Client,
-    url: &str,
+/// Result of a single upstream attempt.
+enum Attempt<T> {
+    Done(T),
+    Retry(GatewayError, RetryOn, Option<Duration>),
+    Fail(GatewayError),
+}
+
+/// Sends `payload` with retries; `read` turns a successful response into the
+/// caller's body type, and a read error counts as an invalid response.
+async fn send_with_retries<T, R, F>(
+    state: &AppState,
+    upstreams: &UpstreamPool,
     payload: Value,
-    attempts: usize,
-) -> Result<Value, String> {
+    policy: &RetryPolicy,
+    read: R,
+) -> Result<T, GatewayError>
+where
+    R: Fn(reqwest::Response) -> F,
+    F: Future<Output = Result<T, GatewayError>>,
+{
+    let limit = state.config.max_response_bytes;
+    let backoff = &state.config.retry_backoff;
+    let attempts = policy.max_attempts.max(1);
+    let deadline = Instant::now() + backoff.deadline;
     let mut last_err = None;
+    let mut tried = Vec::new();
+
+    for attempt in 0..attempts {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
 
-    for attempt in 0..attempts.max(1) {
-        // Each attempt is a fresh POST with the same payload.
-        let response = client.post(url).json(&payload).send().await;
+        // Each attempt is a fresh POST with the same payload, preferably to a new upstream.
+        let Some(index) = upstreams.select(&tried) else {
+            break;
+        };
+        tried.push(index);
+        let upstream = upstreams.get(index);
+        tracing::debug!(upstream = %upstream.name, attempt, "sending upstream request");
 
-        match response {
+        let sent_at = Instant::now();
+        let response = state
+            .client
+            .post(&upstream.url)
+            .timeout(remaining)
+            .json(&payload)
+            .send()
+            .await;
+
+        let outcome = match response {
             Ok(resp) => {
                 let status = resp.status();
-                if status.is_server_error() {
-                    last_err = Some(format!("upstream server error: {}", status));
+                if status == StatusCode::TOO_MANY_REQUESTS {
+                    // Rate limited, not down: keep the circuit closed and retry later.
+                    let retry_after = resp.headers().get(RETRY_AFTER).and_then(parse_retry_after);
+                    report_outcome(state, upstream, true).await;
+                    Attempt::Retry(
+                        GatewayError::UpstreamClientError(status.as_u16()),
+                        RetryOn::RateLimited,
+                        retry_after,
+                    )
+                } else if status.is_server_error() {
+                    report_outcome(state, upstream, false).await;
+                    Attempt::Retry(
+                        GatewayError::UpstreamServerError(status.as_u16()),
+                        RetryOn::ServerError,
+                        None,
+                    )
                 } else if status.is_client_error() {
-                    return Err(format!("upstream client error: {}", status));
+                    // The upstream is up and answering; the request itself was refused.
+                    report_outcome(state, upstream, true).await;
+                    Attempt::Fail(GatewayError::UpstreamClientError(status.as_u16()))
+                } else if resp.content_length().is_some_and(|length| length > limit) {
+                    // Too big is a property of the answer, not of the upstream's health.
+                    report_outcome(state, upstream, true).await;
+                    Attempt::Fail(GatewayError::ResponseTooLarge(limit))
                 } else {
-                    return resp.json::<Value>().await.map_err(|err| err.to_string());
+                    match read(resp).await {
+                        Ok(body) => {
+                            // For streamed bodies this is time to headers, not to the last byte.
+                            upstream.record_latency(sent_at.elapsed());
+                            report_outcome(state, upstream, true).await;
+                            Attempt::Done(body)
+                        }
+                        Err(err @ GatewayError::ResponseTooLarge(_)) => {
+                            report_outcome(state, upstream, true).await;
+                            Attempt::Fail(err)
+                        }
+                        Err(err) => {
+                            report_outcome(state, upstream, false).await;
+                            Attempt::Retry(err, RetryOn::InvalidResponse, None)
+                        }
+                    }
                 }
             }
             Err(err) => {
-                last_err = Some(err.to_string());
+                report_outcome(state, upstream, false).await;
+                let failure = if err.is_connect() {
+                    RetryOn::Connect
+                } else {
+                    RetryOn::Timeout
+                };
+                Attempt::Retry(GatewayError::from(err), failure, None)
+            }
+        };
+
+        let retry_after = match outcome {
+            Attempt::Done(body) => return Ok(body),
+            Attempt::Fail(err) => return Err(err),
+            Attempt::Retry(err, failure, _) if !policy.should_retry(failure) => return Err(err),
+            Attempt::Retry(err, _, retry_after) => {
+                last_err = Some(err);
+                retry_after
             }
+        };
+
+        if attempt + 1 == attempts {
+            break;
         }
 
-        // Linear backoff to reduce upstream pressure.
-        let backoff = Duration::from_millis(BASE_BACKOFF_MS * (attempt as u64 + 1));
-        sleep(backoff).await;
+        // Exponential backoff with full jitter, never shorter than upstream's Retry-After.
+        let delay = backoff
+            .delay(attempt)
+            .max(retry_after.unwrap_or(Duration::ZERO));
+        if Instant::now() + delay >= deadline {
+            break;
+        }
+        sleep(delay).await;
     }
 
-    Err(last_err.unwrap_or_else(|| "request failed".to_string()))
+    Err(last_err.unwrap_or_else(|| {
+        if tried.is_empty() {
+            GatewayError::UpstreamUnavailable(
+                "no upstream available, all circuits open".to_string(),
+            )
+        } else {
+            GatewayError::UpstreamTimeout
+        }
+    }))
+}
+
+/// Reads and decodes a JSON body, giving up once it grows past `limit` bytes.
+async fn read_json(resp: reqwest::Response, limit: u64) -> Result<Value, GatewayError> {
+    let mut body = Vec::new();
+    let mut chunks = resp.bytes_stream();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk
+            .map_err(|err| GatewayError::InvalidUpstreamResponse(err.without_url().to_string()))?;
+        if (body.len() + chunk.len()) as u64 > limit {
+            return Err(GatewayError::ResponseTooLarge(limit));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    serde_json::from_slice(&body)
+        .map_err(|err| GatewayError::InvalidUpstreamResponse(err.to_string()))
+}
+
+async fn report_outcome(state: &AppState, upstream: &Upstream, success: bool) {
+    let event = if success {
+        upstream.breaker.record_success()
+    } else {
+        upstream.breaker.record_failure()
+    };
+    if let Some(event) = event {
+        record_circuit_event(state, upstream, event).await;
+    }
 }
 
-fn hash_value(value: &Value) -> Result<String, String> {
+fn hash_value(value: &Value) -> Result<String, GatewayError> {
     // Hash the canonical JSON string for cache key stability.
-    let payload = serde_json::to_string(value).map_err(|err| err.to_string())?;
+    let payload =
+        serde_json::to_string(value).map_err(|err| GatewayError::Internal(err.to_string()))?;
     let mut hasher = Sha256::new();
     hasher.update(payload.as_bytes());
     Ok(hex::encode(hasher.finalize()))
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::cache::{CacheBackend, CacheLimits, DiskCache, DiskConfig, MemoryCache};
+    use crate::cache_ttl::{NegativeCaching, TtlPolicies};
+    use crate::cache_warmer::WarmingConfig;
+    use crate::circuit_breaker::CircuitBreakerConfig;
+    use crate::commitment::Commitment;
+    use crate::config::Config;
+    use crate::log_events::LogState;
+    use crate::method_pattern::MethodPattern;
+    use crate::method_policy::MethodPolicy;
+    use crate::metrics::Metrics;
+    use crate::privacy_mode::PrivacyMode;
+    use crate::refresh_backoff::RefreshBackoff;
+    use crate::retry::{BackoffConfig, RetryPolicies};
+    use crate::routing::{RouteRule, RouteTable, DEFAULT_POOL};
+    use crate::singleflight::SingleFlight;
+    use crate::upstream::{SelectionStrategy, UpstreamConfig, UpstreamPools};
+    use axum::response::IntoResponse;
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use serde_json::json;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Spawns a fake upstream that answers every call with its method name.
+    async fn spawn_upstream(calls: Arc<AtomicUsize>) -> String {
+        let app = Router::new().route(
+            "/",
+            post(move |Json(body): Json<Value>| {
+                let calls = calls.clone();
+                async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    // Small delay so concurrent callers overlap in flight.
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    let answer = |item: &Value| {
+                        json!({
+                            "jsonrpc": "2.0",
+                            "id": item["id"].clone(),
+                            "result": item["method"].clone()
+                        })
+                    };
+                    match body {
+                        Value::Array(items) => {
+                            // Reply out of order to exercise id-based reassembly.
+                            Json(Value::Array(items.iter().rev().map(answer).collect()))
+                        }
+                        single => Json(answer(&single)),
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    pub(crate) fn test_state(url: String, mode: PrivacyMode) -> AppState {
+        let pools = HashMap::from([(
+            DEFAULT_POOL.to_string(),
+            vec![UpstreamConfig::from_url(url)],
+        )]);
+        test_state_with_pools(pools, RouteTable::default(), mode)
+    }
+
+    fn test_state_with_pools(
+        upstream_pools: HashMap<String, Vec<UpstreamConfig>>,
+        routes: RouteTable,
+        mode: PrivacyMode,
+    ) -> AppState {
+        let config = Config {
+            upstream_pools,
+            routes,
+            upstream_strategy: SelectionStrategy::RoundRobin,
+            circuit_breaker: CircuitBreakerConfig::default(),
+            health_check_interval: None,
+            quicknode_ws_url: None,
+            slot_invalidation: false,
+            account_invalidation: false,
+            account_watch_limit: 0,
+            privacy_mode: mode,
+            method_policy: MethodPolicy::for_mode(mode),
+            cache_ttls: TtlPolicies::for_mode(mode, Duration::from_secs(60)),
+            negative_cache: NegativeCaching::new(Duration::ZERO, HashSet::new()),
+            default_commitment: Commitment::Finalized,
+            cache_limits: CacheLimits {
+                max_entries: 1_000,
+                max_bytes: 1024 * 1024,
+            },
+            cache_backend: CacheBackend::Memory,
+            cache_stale_grace: Duration::ZERO,
+            cache_compress_min_bytes: None,
+            cache_warming: WarmingConfig::default(),
+            cache_snapshot_file: None,
+            cache_sweep_interval: None,
+            request_timeout: Duration::from_secs(5),
+            max_response_bytes: 1024 * 1024,
+            retry_policies: RetryPolicies::solana_defaults(1),
+            retry_backoff: BackoffConfig {
+                base: Duration::from_millis(10),
+                max: Duration::from_millis(50),
+                deadline: Duration::from_secs(5),
+            },
+            bind_addr: "127.0.0.1:0".to_string(),
+            admin_token: None,
+        };
+        AppState {
+            cache: Arc::new(MemoryCache::new(config.cache_limits)),
+            upstreams: Arc::new(UpstreamPools::new(
+                &config.upstream_pools,
+                config.upstream_strategy,
+                config.circuit_breaker.clone(),
+            )),
+            config: Arc::new(config),
+            flights: SingleFlight::new(),
+            refresh_backoff: RefreshBackoff::new(),
+            metrics: Arc::new(Metrics::new()),
+            log_state: Arc::new(LogState::new(100, 16)),
+            account_watcher: None,
+            warmer: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Runs a request and decodes the answer, collecting streamed bodies; no
+    /// answer at all comes back as `null`.
+    async fn rpc(state: AppState, payload: Value) -> Result<Value, GatewayError> {
+        match handle_rpc_request(state, payload).await? {
+            RpcResponse::Json(value) => Ok(value),
+            RpcResponse::Empty => Ok(Value::Null),
+            RpcResponse::Stream(body) => {
+                let chunks: Vec<_> = body.collect().await;
+                let mut bytes = Vec::new();
+                for chunk in chunks {
+                    bytes.extend_from_slice(&chunk?);
+                }
+                serde_json::from_slice(&bytes)
+                    .map_err(|err| GatewayError::InvalidUpstreamResponse(err.to_string()))
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_preserves_order_and_ids() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let state = test_state(spawn_upstream(calls.clone()).await, PrivacyMode::Strict);
+
+        let batch = json!([
+            {"jsonrpc": "2.0", "id": "a", "method": "getSlot", "params": []},
+            {"jsonrpc": "2.0", "id": 7, "method": "getHealth"},
+            {"jsonrpc": "2.0", "id": "c", "method": "getLatestBlockhash", "params": []}
+        ]);
+        let response = rpc(state, batch).await.unwrap();
+        let items = response.as_array().unwrap();
+
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0]["id"], "a");
+        assert_eq!(items[0]["result"], "getSlot");
+        assert_eq!(items[1]["id"], 7);
+        assert_eq!(items[1]["result"], "getHealth");
+        assert_eq!(items[2]["id"], "c");
+        assert_eq!(items[2]["result"], "getLatestBlockhash");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_batch_of_notifications_has_no_response() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let state = test_state(spawn_upstream(calls.clone()).await, PrivacyMode::Strict);
+
+        let batch = json!([
+            {"jsonrpc": "2.0", "method": "getSlot", "params": []},
+            {"jsonrpc": "2.0", "method": "getHealth"}
+        ]);
+        let response = handle_rpc_request(state, batch).await.unwrap();
+        assert!(matches!(response, RpcResponse::Empty));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_requests_never_reach_upstream() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let state = test_state(spawn_upstream(calls.clone()).await, PrivacyMode::Strict);
+
+        for payload in [
+            json!("getSlot"),
+            json!({"jsonrpc": "2.0", "id": 1, "method": 5}),
+        ] {
+            let err = rpc(state.clone(), payload).await.unwrap_err();
+            assert_eq!(err.code(), crate::error::INVALID_REQUEST);
+        }
+
+        let batch = json!([
+            {"jsonrpc": "2.0", "id": "a", "method": "getSlot", "params": []},
+            {"jsonrpc": "2.0", "id": "b", "params": []}
+        ]);
+        let response = rpc(state, batch).await.unwrap();
+        assert_eq!(response[0]["result"], "getSlot");
+        assert_eq!(response[1]["id"], "b");
+        assert_eq!(response[1]["error"]["code"], crate::error::INVALID_REQUEST);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_finalized_transactions_persist_on_disk() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let url = spawn_upstream(calls.clone()).await;
+        let dir = std::env::temp_dir().join(format!("qn-gateway-proxy-tx-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let disk_state = || {
+            let mut state = test_state(url.clone(), PrivacyMode::Strict);
+            let disk = DiskConfig {
+                dir: dir.clone(),
+                max_bytes: 1024 * 1024,
+                min_ttl: Duration::from_secs(60),
+            };
+            state.cache =
+                Arc::new(DiskCache::open(state.config.cache_limits, Duration::ZERO, disk).unwrap());
+            state
+        };
+        let payload =
+            json!({"jsonrpc": "2.0", "id": 1, "method": "getTransaction", "params": ["sig"]});
+
+        let response = rpc(disk_state(), payload.clone()).await.unwrap();
+        assert_eq!(response["result"], "getTransaction");
+
+        // A new cache over the same directory stands in for a restart.
+        let response = rpc(disk_state(), payload).await.unwrap();
+        assert_eq!(response["result"], "getTransaction");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_batch_only_forwards_cache_misses() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let state = test_state(spawn_upstream(calls.clone()).await, PrivacyMode::Strict);
+
+        let warm = json!({"jsonrpc": "2.0", "id": 1, "method": "getSlot", "params": []});
+        rpc(state.clone(), warm).await.unwrap();
+
+        let batch = json!([
+            {"jsonrpc": "2.0", "id": 10, "method": "getSlot", "params": []},
+            {"jsonrpc": "2.0", "id": 11, "method": "getBalance", "params": ["addr"]}
+        ]);
+        let response = rpc(state.clone(), batch.clone()).await.unwrap();
+        assert_eq!(response[0]["id"], 10);
+        assert_eq!(response[1]["id"], 11);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Every element is now cached, so the batch never reaches upstream.
+        rpc(state, batch).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_batch_upstream_failure_yields_per_element_errors() {
+        let state = test_state("http://127.0.0.1:1".to_string(), PrivacyMode::Strict);
+
+        let batch = json!([
+            {"jsonrpc": "2.0", "id": 1, "method": "getSlot"},
+            {"jsonrpc": "2.0", "id": 2, "method": "getHealth"}
+        ]);
+        let response = rpc(state, batch).await.unwrap();
+
+        assert_eq!(response[0]["id"], 1);
+        assert_eq!(response[1]["id"], 2);
+        assert_eq!(
+            response[1]["error"]["code"],
+            crate::error::UPSTREAM_UNAVAILABLE
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cache_hit_answers_with_caller_id() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let state = test_state(spawn_upstream(calls.clone()).await, PrivacyMode::Strict);
+
+        let first = json!({"jsonrpc": "2.0", "id": "client-a", "method": "getSlot"});
+        let second = json!({"jsonrpc": "2.0", "id": 42, "method": "getSlot"});
+        let hash = RpcCall::prepare(&state.config, first.clone())
+            .unwrap()
+            .request_hash;
+
+        let response = rpc(state.clone(), first).await.unwrap();
+        assert_eq!(response["id"], "client-a");
+
+        let response = rpc(state.clone(), second).await.unwrap();
+        assert_eq!(response["id"], 42);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let stored = state.cache.get(&hash).await.unwrap();
+        assert!(stored.get("id").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stale_hit_is_served_while_refreshing_once() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut state = test_state(spawn_upstream(calls.clone()).await, PrivacyMode::Strict);
+        state.cache = Arc::new(
+            MemoryCache::new(state.config.cache_limits).with_stale_grace(Duration::from_secs(60)),
+        );
+
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": "getSlot"});
+        let hash = RpcCall::prepare(&state.config, request.clone())
+            .unwrap()
+            .request_hash;
+        state
+            .cache
+            .insert(
+                hash.clone(),
+                "getSlot",
+                json!({"jsonrpc": "2.0", "result": "old"}),
+                Duration::ZERO,
+            )
+            .await;
+
+        // Both callers get the stale answer; only the first starts a refresh.
+        for _ in 0..2 {
+            let response = rpc(state.clone(), request.clone()).await.unwrap();
+            assert_eq!(response["result"], "old");
+        }
+        let events = state.log_state.recent(100).await;
+        let notes: Vec<_> = events
+            .iter()
+            .filter(|event| event.event == "CACHE_HIT")
+            .filter_map(|event| event.note.clone())
+            .collect();
+        assert_eq!(notes.len(), 2);
+        assert!(notes.iter().all(|note| note.starts_with("stale ")));
+        let cache_stats = state.cache.stats().await;
+        assert_eq!(state.metrics.snapshot(cache_stats).await["stale_hits"], 2);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let refreshed = state.cache.get(&hash).await.unwrap();
+        assert_eq!(refreshed["result"], "getSlot");
+    }
+
+    #[tokio::test]
+    async fn test_failed_refresh_is_not_retried_within_grace() {
+        // Nothing listens on port 1, so every refresh fails.
+        let mut state = test_state("http://127.0.0.1:1".to_string(), PrivacyMode::Strict);
+        let mut config = (*state.config).clone();
+        config.cache_stale_grace = Duration::from_secs(60);
+        state.config = Arc::new(config);
+        state.cache = Arc::new(
+            MemoryCache::new(state.config.cache_limits).with_stale_grace(Duration::from_secs(60)),
+        );
+
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": "getSlot"});
+        let hash = RpcCall::prepare(&state.config, request.clone())
+            .unwrap()
+            .request_hash;
+        state
+            .cache
+            .insert(
+                hash,
+                "getSlot",
+                json!({"jsonrpc": "2.0", "result": "old"}),
+                Duration::ZERO,
+            )
+            .await;
+
+        let response = rpc(state.clone(), request.clone()).await.unwrap();
+        assert_eq!(response["result"], "old");
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        for _ in 0..3 {
+            let response = rpc(state.clone(), request.clone()).await.unwrap();
+            assert_eq!(response["result"], "old");
+        }
+
+        let events = state.log_state.recent(100).await;
+        let forwarded = events
+            .iter()
+            .filter(|event| event.event == "FORWARDED")
+            .count();
+        assert_eq!(forwarded, 1);
+        let failed = events
+            .iter()
+            .filter_map(|event| event.note.as_deref())
+            .filter(|note| note.ends_with("refresh failed"))
+            .count();
+        assert_eq!(failed, 3);
+    }
+
+    #[tokio::test]
+    async fn test_warmed_entry_answers_clients() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let state = test_state(spawn_upstream(calls.clone()).await, PrivacyMode::Strict);
+
+        // Learned requests are kept without their id.
+        let warmed = warm_cache(&state, json!({"jsonrpc": "2.0", "method": "getSlot"}))
+            .await
+            .unwrap();
+        assert_eq!(warmed, Some(Duration::from_secs(1)));
+
+        let request = json!({"jsonrpc": "2.0", "id": 9, "method": "getSlot"});
+        let response = rpc(state.clone(), request).await.unwrap();
+        assert_eq!(response["result"], "getSlot");
+        assert_eq!(response["id"], 9);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // The privacy mode decides what can be warmed.
+        let uncached = json!({"jsonrpc": "2.0", "method": "sendTransaction"});
+        assert_eq!(cache_key(&state.config, uncached.clone()).unwrap(), None);
+        assert_eq!(warm_cache(&state, uncached).await.unwrap(), None);
+
+        // So does the method policy, before anything reaches upstream.
+        let mut config = (*state.config).clone();
+        config.method_policy = MethodPolicy::for_mode(PrivacyMode::Strict)
+            .with_overrides(serde_json::from_value(json!({"deny": ["getBalance"]})).unwrap());
+        let denied = AppState {
+            config: Arc::new(config),
+            ..state
+        };
+        let balance = json!({"jsonrpc": "2.0", "method": "getBalance", "params": ["acct"]});
+        let err = warm_cache(&denied, balance).await.unwrap_err();
+        assert_eq!(err.code(), crate::error::POLICY_REJECTED);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_moves_to_another_upstream() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let healthy = spawn_upstream(calls.clone()).await;
+        let pools = HashMap::from([(
+            DEFAULT_POOL.to_string(),
+            vec![
+                UpstreamConfig::from_url("http://127.0.0.1:1".to_string()),
+                UpstreamConfig::from_url(healthy),
+            ],
+        )]);
+        let mut state = test_state_with_pools(pools, RouteTable::default(), PrivacyMode::Dev);
+
+        let mut config = (*state.config).clone();
+        config.retry_policies = RetryPolicies::solana_defaults(2);
+        state.config = Arc::new(config);
+
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": "getSlot"});
+        let response = rpc(state, request).await.unwrap();
+
+        assert_eq!(response["result"], "getSlot");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_request_is_retried() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let app = Router::new().route(
+            "/",
+            post(move || {
+                let counter = counter.clone();
+                async move {
+                    // First call is rate limited, the retry succeeds.
+                    if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                        (
+                            axum::http::StatusCode::TOO_MANY_REQUESTS,
+                            [("retry-after", "0")],
+                            Json(json!({})),
+                        )
+                            .into_response()
+                    } else {
+                        Json(json!({"jsonrpc": "2.0", "id": 1, "result": 5})).into_response()
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut state = test_state(url, PrivacyMode::Dev);
+        let mut config = (*state.config).clone();
+        config.retry_policies = RetryPolicies::solana_defaults(2);
+        state.config = Arc::new(config);
+
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": "getSlot"});
+        let response = rpc(state, request).await.unwrap();
+
+        assert_eq!(response["result"], 5);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_send_transaction_is_not_retried_on_server_error() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let app = Router::new().route(
+            "/",
+            post(move || {
+                let counter = counter.clone();
+                async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    axum::http::StatusCode::SERVICE_UNAVAILABLE
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut state = test_state(url, PrivacyMode::Dev);
+        let mut config = (*state.config).clone();
+        config.retry_policies = RetryPolicies::solana_defaults(3);
+        state.config = Arc::new(config);
+
+        let send =
+            json!({"jsonrpc": "2.0", "id": 1, "method": "sendTransaction", "params": ["tx"]});
+        let err = rpc(state.clone(), send).await.unwrap_err();
+        assert_eq!(err, GatewayError::UpstreamServerError(503));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Reads keep the full retry budget.
+        let read = json!({"jsonrpc": "2.0", "id": 2, "method": "getSlot"});
+        rpc(state, read).await.unwrap_err();
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_negative_answers_are_cached_briefly() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let app = Router::new().route(
+            "/",
+            post(move |Json(body): Json<Value>| {
+                let counter = counter.clone();
+                async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    Json(match body["method"].as_str() {
+                        Some("getAccountInfo") => json!({
+                            "jsonrpc": "2.0",
+                            "id": body["id"],
+                            "result": {"context": {"slot": 1}, "value": null}
+                        }),
+                        _ => json!({
+                            "jsonrpc": "2.0",
+                            "id": body["id"],
+                            "error": {"code": -32602, "message": "Invalid param"}
+                        }),
+                    })
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut state = test_state(url.clone(), PrivacyMode::Strict);
+        let mut config = (*state.config).clone();
+        config.negative_cache =
+            NegativeCaching::new(Duration::from_secs(1), HashSet::from([-32602]));
+        state.config = Arc::new(config);
+
+        let missing =
+            json!({"jsonrpc": "2.0", "id": 1, "method": "getAccountInfo", "params": ["acct"]});
+        let invalid = json!({"jsonrpc": "2.0", "id": 2, "method": "getBalance", "params": ["x"]});
+        for _ in 0..2 {
+            let response = rpc(state.clone(), missing.clone()).await.unwrap();
+            assert!(response["result"]["value"].is_null());
+            let response = rpc(state.clone(), invalid.clone()).await.unwrap();
+            assert_eq!(response["error"]["code"], -32602);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let cache_stats = state.cache.stats().await;
+        let metrics = state.metrics.snapshot(cache_stats).await;
+        assert_eq!(metrics["negative_hits"], 2);
+        assert_eq!(metrics["cache_hits"], 2);
+
+        // Both were stored with the negative TTL rather than the method's.
+        let events = state.log_state.recent(100).await;
+        assert!(events
+            .iter()
+            .filter(|event| event.event == "RESP_OUT")
+            .all(|event| event.ttl_ms == Some(1_000)));
+
+        // Without negative caching the `null` keeps the method's TTL, and hits
+        // on it are ordinary ones.
+        let plain = test_state(url, PrivacyMode::Strict);
+        for _ in 0..2 {
+            rpc(plain.clone(), missing.clone()).await.unwrap();
+        }
+        let cache_stats = plain.cache.stats().await;
+        let metrics = plain.metrics.snapshot(cache_stats).await;
+        assert_eq!(metrics["cache_hits"], 1);
+        assert_eq!(metrics["negative_hits"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_identical_reads_are_coalesced() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let state = test_state(spawn_upstream(calls.clone()).await, PrivacyMode::Strict);
+
+        let tasks: Vec<_> = (0..5)
+            .map(|id| {
+                let state = state.clone();
+                let request = json!({"jsonrpc": "2.0", "id": id, "method": "getLatestBlockhash"});
+                tokio::spawn(rpc(state, request))
+            })
+            .collect();
+
+        for (id, task) in tasks.into_iter().enumerate() {
+            let response = task.await.unwrap().unwrap();
+            assert_eq!(response["id"], id);
+            assert_eq!(response["result"], "getLatestBlockhash");
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_routed_methods_use_their_pool() {
+        let default_calls = Arc::new(AtomicUsize::new(0));
+        let archive_calls = Arc::new(AtomicUsize::new(0));
+        let pools = HashMap::from([
+            (
+                DEFAULT_POOL.to_string(),
+                vec![UpstreamConfig::from_url(
+                    spawn_upstream(default_calls.clone()).await,
+                )],
+            ),
+            (
+                "archive".to_string(),
+                vec![UpstreamConfig::from_url(
+                    spawn_upstream(archive_calls.clone()).await,
+                )],
+            ),
+        ]);
+        let routes = RouteTable::new(vec![RouteRule {
+            methods: vec![MethodPattern::new("getBlock*")],
+            pool: "archive".to_string(),
+        }]);
+        let state = test_state_with_pools(pools, routes, PrivacyMode::Dev);
+
+        let batch = json!([
+            {"jsonrpc": "2.0", "id": 1, "method": "getSlot"},
+            {"jsonrpc": "2.0", "id": 2, "method": "getBlock", "params": [1]},
+            {"jsonrpc": "2.0", "id": 3, "method": "getBlockTime", "params": [1]}
+        ]);
+        let response = rpc(state.clone(), batch).await.unwrap();
+
+        assert_eq!(response[0]["result"], "getSlot");
+        assert_eq!(response[1]["result"], "getBlock");
+        assert_eq!(response[2]["result"], "getBlockTime");
+        assert_eq!(default_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(archive_calls.load(Ordering::SeqCst), 1);
+
+        let snapshot = state.metrics.snapshot(state.cache.stats().await).await;
+        assert_eq!(snapshot["pool_requests"]["archive"], 2);
+        assert_eq!(snapshot["pool_requests"][DEFAULT_POOL], 1);
+    }
+
+    #[tokio::test]
+    async fn test_uncacheable_response_is_streamed() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let state = test_state(spawn_upstream(calls.clone()).await, PrivacyMode::Dev);
+
+        let request = json!({"jsonrpc": "2.0", "id": "p", "method": "getProgramAccounts"});
+        let response = handle_rpc_request(state.clone(), request).await.unwrap();
+        let RpcResponse::Stream(body) = response else {
+            panic!("expected a streamed response");
+        };
+        let bytes: Vec<u8> = body.map(|chunk| chunk.unwrap().to_vec()).concat().await;
+        let decoded: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(decoded["id"], "p");
+        assert_eq!(decoded["result"], "getProgramAccounts");
+
+        // RESP_OUT is recorded from a spawned task once the body completes.
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let events = state.log_state.recent(100).await;
+        assert!(events.iter().any(|event| event.event == "RESP_OUT"));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_response_is_rejected() {
+        let large = "x".repeat(4096);
+        let app = Router::new().route(
+            "/",
+            post(move || {
+                let large = large.clone();
+                async move { Json(json!({"jsonrpc": "2.0", "id": 1, "result": large})) }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        for mode in [PrivacyMode::Dev, PrivacyMode::Strict] {
+            let mut state = test_state(url.clone(), mode);
+            let mut config = (*state.config).clone();
+            config.max_response_bytes = 1024;
+            state.config = Arc::new(config);
+
+            let request = json!({"jsonrpc": "2.0", "id": 1, "method": "getSlot"});
+            let err = rpc(state, request).await.unwrap_err();
+            assert_eq!(err, GatewayError::ResponseTooLarge(1024));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_method_ttl_is_reported_in_events() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let state = test_state(spawn_upstream(calls.clone()).await, PrivacyMode::Balanced);
+
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": "getLatestBlockhash"});
+        rpc(state.clone(), request).await.unwrap();
+
+        let events = state.log_state.recent(100).await;
+        let ttl_of = |name: &str| {
+            events
+                .iter()
+                .find(|event| event.event == name)
+                .and_then(|event| event.ttl_ms)
+        };
+        assert_eq!(ttl_of("CACHE_MISS"), Some(1_000));
+        assert_eq!(ttl_of("RESP_OUT"), Some(1_000));
+    }
+
+    #[tokio::test]
+    async fn test_commitment_decides_cache_sharing() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let state = test_state(spawn_upstream(calls.clone()).await, PrivacyMode::Strict);
+
+        // Omitted and explicit default commitment share one entry.
+        let omitted =
+            json!({"jsonrpc": "2.0", "id": 1, "method": "getBalance", "params": ["addr"]});
+        let explicit = json!({
+            "jsonrpc": "2.0", "id": 2, "method": "getBalance",
+            "params": ["addr", {"commitment": "finalized"}]
+        });
+        rpc(state.clone(), omitted).await.unwrap();
+        rpc(state.clone(), explicit).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Processed reads are never cached.
+        let processed = json!({
+            "jsonrpc": "2.0", "id": 3, "method": "getBalance",
+            "params": ["addr", {"commitment": "processed"}]
+        });
+        rpc(state.clone(), processed.clone()).await.unwrap();
+        rpc(state, processed).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_denied_methods_never_reach_upstream() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let state = test_state(spawn_upstream(calls.clone()).await, PrivacyMode::Strict);
+
+        let scan = json!({"jsonrpc": "2.0", "id": 1, "method": "getProgramAccounts"});
+        let err = rpc(state.clone(), scan).await.unwrap_err();
+        assert_eq!(
+            err,
+            GatewayError::PolicyRejected("method getProgramAccounts is denied".to_string())
+        );
+
+        // Only the refused element of a batch gets the error.
+        let batch = json!([
+            {"jsonrpc": "2.0", "id": "a", "method": "getSlot", "params": []},
+            {"jsonrpc": "2.0", "id": "b", "method": "requestAirdrop", "params": ["addr", 1]}
+        ]);
+        let response = rpc(state.clone(), batch).await.unwrap();
+        assert_eq!(response[0]["result"], "getSlot");
+        assert_eq!(response[1]["id"], "b");
+        assert_eq!(response[1]["error"]["code"], crate::error::POLICY_REJECTED);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let events = state.log_state.recent(100).await;
+        let denials: Vec<_> = events
+            .iter()
+            .filter(|event| event.event == "POLICY_DENY")
+            .filter_map(|event| event.method.as_deref())
+            .collect();
+        assert_eq!(denials, ["getProgramAccounts", "requestAirdrop"]);
+    }
+}
@@ -0,0 +1,64 @@
+//! Keys whose background refresh of a stale entry failed, so further stale
+//! hits do not each start another upstream call.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+#[derive(Clone, Default)]
+pub struct RefreshBackoff {
+    /// When each key may be refreshed again.
+    retry_at: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl RefreshBackoff {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a refresh of `key` failed recently enough that none may start.
+    pub fn is_backing_off(&self, key: &str) -> bool {
+        let mut retry_at = self.retry_at.lock().expect("refresh backoff lock");
+        match retry_at.get(key) {
+            Some(at) if Instant::now() < *at => true,
+            Some(_) => {
+                retry_at.remove(key);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Holds off refreshing `key` until `until`; passed deadlines are dropped
+    /// so the map only holds keys still backing off.
+    pub fn record_failure(&self, key: &str, until: Instant) {
+        let now = Instant::now();
+        let mut retry_at = self.retry_at.lock().expect("refresh backoff lock");
+        retry_at.retain(|_, at| *at > now);
+        if until > now {
+            retry_at.insert(key.to_string(), until);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_backoff_ends_at_deadline() {
+        let backoff = RefreshBackoff::new();
+        backoff.record_failure("a", Instant::now() + Duration::from_secs(60));
+        backoff.record_failure("b", Instant::now());
+
+        assert!(backoff.is_backing_off("a"));
+        assert!(!backoff.is_backing_off("b"));
+        assert!(!backoff.is_backing_off("c"));
+
+        backoff.record_failure("a", Instant::now() + Duration::from_millis(10));
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!backoff.is_backing_off("a"));
+        assert!(backoff.retry_at.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,272 @@
+//! Retry policy: per-method retry rules, exponential backoff with full jitter
+//! and `Retry-After` parsing.
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::header::HeaderValue;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Failure classes a retry policy can opt into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryOn {
+    /// The connection could not be established; upstream never saw the request.
+    Connect,
+    /// The request may have reached upstream but no answer came back in time
+    /// (timeouts and connections dropped mid-request).
+    Timeout,
+    /// Upstream answered with HTTP 5xx.
+    ServerError,
+    /// Upstream answered with HTTP 429 and refused the request.
+    RateLimited,
+    /// Upstream answered 2xx with a body that could not be decoded.
+    InvalidResponse,
+}
+
+impl RetryOn {
+    pub const ALL: [RetryOn; 5] = [
+        RetryOn::Connect,
+        RetryOn::Timeout,
+        RetryOn::ServerError,
+        RetryOn::RateLimited,
+        RetryOn::InvalidResponse,
+    ];
+
+    /// Whether upstream is known not to have processed the request.
+    fn before_delivery(self) -> bool {
+        matches!(self, RetryOn::Connect | RetryOn::RateLimited)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: usize,
+    pub retry_on: Vec<RetryOn>,
+    /// Non-idempotent calls are only retried when upstream provably never processed them.
+    pub idempotent: bool,
+}
+
+impl RetryPolicy {
+    pub fn should_retry(&self, failure: RetryOn) -> bool {
+        self.retry_on.contains(&failure) && (self.idempotent || failure.before_delivery())
+    }
+
+    /// The stricter of two policies, used when several calls share one upstream request.
+    pub fn combine(&self, other: &RetryPolicy) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts.min(other.max_attempts),
+            retry_on: self
+                .retry_on
+                .iter()
+                .copied()
+                .filter(|failure| other.retry_on.contains(failure))
+                .collect(),
+            idempotent: self.idempotent && other.idempotent,
+        }
+    }
+}
+
+/// Partial policy from the config file; unset fields keep the built-in value.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicyOverride {
+    pub max_attempts: Option<usize>,
+    pub retry_on: Option<Vec<RetryOn>>,
+    pub idempotent: Option<bool>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicies {
+    default: RetryPolicy,
+    methods: HashMap<String, RetryPolicy>,
+}
+
+impl RetryPolicies {
+    /// Solana defaults: reads retry on everything, transactions only on connect errors.
+    pub fn solana_defaults(attempts: usize) -> Self {
+        let attempts = attempts.max(1);
+        let default = RetryPolicy {
+            max_attempts: attempts,
+            retry_on: RetryOn::ALL.to_vec(),
+            idempotent: true,
+        };
+
+        let mut methods = HashMap::new();
+        methods.insert(
+            "sendTransaction".to_string(),
+            RetryPolicy {
+                max_attempts: attempts,
+                retry_on: vec![RetryOn::Connect],
+                idempotent: false,
+            },
+        );
+        methods.insert(
+            "requestAirdrop".to_string(),
+            RetryPolicy {
+                max_attempts: 1,
+                retry_on: Vec::new(),
+                idempotent: false,
+            },
+        );
+
+        Self { default, methods }
+    }
+
+    pub fn with_overrides(mut self, overrides: HashMap<String, RetryPolicyOverride>) -> Self {
+        for (method, update) in overrides {
+            let mut policy = self.for_method(&method).clone();
+            if let Some(max_attempts) = update.max_attempts {
+                policy.max_attempts = max_attempts.max(1);
+            }
+            if let Some(retry_on) = update.retry_on {
+                policy.retry_on = retry_on;
+            }
+            if let Some(idempotent) = update.idempotent {
+                policy.idempotent = idempotent;
+            }
+            self.methods.insert(method, policy);
+        }
+        self
+    }
+
+    pub fn for_method(&self, method: &str) -> &RetryPolicy {
+        self.methods.get(method).unwrap_or(&self.default)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BackoffConfig {
+    /// Backoff ceiling for the first retry; doubles on every further attempt.
+    pub base: Duration,
+    /// Upper bound for a single backoff delay.
+    pub max: Duration,
+    /// Total time budget for all attempts and delays of one upstream call.
+    pub deadline: Duration,
+}
+
+impl BackoffConfig {
+    /// Full-jitter delay before retry number `attempt` (0-based).
+    pub fn delay(&self, attempt: usize) -> Duration {
+        let ceiling = self.ceiling(attempt);
+        if ceiling.is_zero() {
+            return ceiling;
+        }
+        let millis = rand::thread_rng().gen_range(0..=ceiling.as_millis() as u64);
+        Duration::from_millis(millis)
+    }
+
+    fn ceiling(&self, attempt: usize) -> Duration {
+        let factor = 1u32.checked_shl(attempt.min(31) as u32).unwrap_or(u32::MAX);
+        self.base.saturating_mul(factor).min(self.max)
+    }
+}
+
+/// Parses a `Retry-After` header given either as delay-seconds or as an HTTP date.
+pub fn parse_retry_after(value: &HeaderValue) -> Option<Duration> {
+    let value = value.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = at.with_timezone(&Utc) - Utc::now();
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backoff() -> BackoffConfig {
+        BackoffConfig {
+            base: Duration::from_millis(100),
+            max: Duration::from_millis(1_000),
+            deadline: Duration::from_secs(5),
+        }
+    }
+
+    #[test]
+    fn test_solana_defaults() {
+        let policies = RetryPolicies::solana_defaults(3);
+
+        let read = policies.for_method("getAccountInfo");
+        assert_eq!(read.max_attempts, 3);
+        assert!(read.should_retry(RetryOn::Timeout));
+
+        let send = policies.for_method("sendTransaction");
+        assert!(send.should_retry(RetryOn::Connect));
+        assert!(!send.should_retry(RetryOn::Timeout));
+        assert!(!send.should_retry(RetryOn::ServerError));
+
+        let airdrop = policies.for_method("requestAirdrop");
+        assert_eq!(airdrop.max_attempts, 1);
+        assert!(!airdrop.should_retry(RetryOn::Connect));
+    }
+
+    #[test]
+    fn test_overrides_merge_onto_builtin_policy() {
+        let overrides = HashMap::from([(
+            "sendTransaction".to_string(),
+            RetryPolicyOverride {
+                retry_on: Some(vec![RetryOn::Connect, RetryOn::Timeout]),
+                ..RetryPolicyOverride::default()
+            },
+        )]);
+        let policies = RetryPolicies::solana_defaults(3).with_overrides(overrides);
+        let send = policies.for_method("sendTransaction");
+
+        // Still non-idempotent, so a timeout is not retried unless that is overridden too.
+        assert_eq!(send.max_attempts, 3);
+        assert!(!send.idempotent);
+        assert!(!send.should_retry(RetryOn::Timeout));
+    }
+
+    #[test]
+    fn test_combine_takes_stricter_policy() {
+        let policies = RetryPolicies::solana_defaults(4);
+        let combined = policies
+            .for_method("getSlot")
+            .combine(policies.for_method("sendTransaction"));
+
+        assert_eq!(combined.max_attempts, 4);
+        assert_eq!(combined.retry_on, vec![RetryOn::Connect]);
+        assert!(!combined.idempotent);
+    }
+
+    #[test]
+    fn test_backoff_ceiling_doubles_and_caps() {
+        let backoff = backoff();
+
+        assert_eq!(backoff.ceiling(0), Duration::from_millis(100));
+        assert_eq!(backoff.ceiling(1), Duration::from_millis(200));
+        assert_eq!(backoff.ceiling(3), Duration::from_millis(800));
+        assert_eq!(backoff.ceiling(4), Duration::from_millis(1_000));
+        assert_eq!(backoff.ceiling(64), Duration::from_millis(1_000));
+    }
+
+    #[test]
+    fn test_backoff_delay_stays_within_ceiling() {
+        let backoff = backoff();
+
+        for attempt in 0..8 {
+            assert!(backoff.delay(attempt) <= backoff.ceiling(attempt));
+        }
+    }
+
+    #[test]
+    fn test_parse_retry_after_seconds() {
+        let value = HeaderValue::from_static("3");
+
+        assert_eq!(parse_retry_after(&value), Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn test_parse_retry_after_past_date_is_zero() {
+        let value = HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT");
+
+        assert_eq!(parse_retry_after(&value), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after(&HeaderValue::from_static("soon")), None);
+    }
+}
@@ -0,0 +1,64 @@
+//! Method-based routing of RPC calls to named upstream pools.
+
+use crate::method_pattern::MethodPattern;
+use serde::Deserialize;
+
+/// Pool that serves every method without a matching route.
+pub const DEFAULT_POOL: &str = "default";
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RouteRule {
+    pub methods: Vec<MethodPattern>,
+    pub pool: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RouteTable {
+    rules: Vec<RouteRule>,
+}
+
+impl RouteTable {
+    pub fn new(rules: Vec<RouteRule>) -> Self {
+        Self { rules }
+    }
+
+    pub fn rules(&self) -> &[RouteRule] {
+        &self.rules
+    }
+
+    /// Pool for `method`; rules are evaluated in order and the first match wins.
+    pub fn pool_for(&self, method: &str) -> &str {
+        self.rules
+            .iter()
+            .find(|rule| rule.methods.iter().any(|pattern| pattern.matches(method)))
+            .map(|rule| rule.pool.as_str())
+            .unwrap_or(DEFAULT_POOL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let table = RouteTable::new(vec![
+            RouteRule {
+                methods: vec![MethodPattern::new("getBlock")],
+                pool: "archive".to_string(),
+            },
+            RouteRule {
+                methods: vec![
+                    MethodPattern::new("getProgramAccounts"),
+                    MethodPattern::new("getBlock*"),
+                ],
+                pool: "heavy".to_string(),
+            },
+        ]);
+
+        assert_eq!(table.pool_for("getBlock"), "archive");
+        assert_eq!(table.pool_for("getBlockHeight"), "heavy");
+        assert_eq!(table.pool_for("getProgramAccounts"), "heavy");
+        assert_eq!(table.pool_for("getSlot"), DEFAULT_POOL);
+    }
+}
@@ -1,14 +1,28 @@
 //! HTTP routing and request handlers.
 
-use crate::cache::Cache;
+use crate::account_watcher::AccountWatcher;
+use crate::admin::admin_routes;
+use crate::cache::{open_cache, run_sweeper, Cache};
+use crate::cache_snapshot::import_snapshot_file;
+use crate::cache_warmer::{run_cache_warming, CacheWarmer};
 use crate::config::Config;
 use crate::dashboard::dashboard_routes;
+use crate::error::GatewayError;
+use crate::health::run_health_checks;
 use crate::log_events::LogState;
 use crate::metrics::Metrics;
-use crate::proxy::handle_rpc_request;
+use crate::proxy::{handle_rpc_request, RpcResponse};
+use crate::refresh_backoff::RefreshBackoff;
+use crate::singleflight::SingleFlight;
+use crate::slot_watcher::run_slot_invalidation;
+use crate::upstream::UpstreamPools;
+use axum::body::Body;
+use axum::extract::rejection::JsonRejection;
 use axum::extract::ws::WebSocketUpgrade;
 use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
 use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
 use axum::routing::{get, post};
 use axum::Json;
 use axum::Router;
@@ -19,40 +33,118 @@ use std::sync::Arc;
 #[derive(Clone)]
 pub struct AppState {
     pub config: Arc<Config>,
-    pub cache: Arc<Cache>,
+    pub cache: Arc<dyn Cache>,
+    pub upstreams: Arc<UpstreamPools>,
+    pub flights: SingleFlight,
+    /// Stale entries whose last background refresh failed.
+    pub refresh_backoff: RefreshBackoff,
     pub metrics: Arc<Metrics>,
     pub log_state: Arc<LogState>,
+    /// Present when cached account reads are invalidated from `accountSubscribe`.
+    pub account_watcher: Option<AccountWatcher>,
+    /// Present when hot requests are refreshed ahead of expiry.
+    pub warmer: Option<Arc<CacheWarmer>>,
     pub client: Client,
 }
 
-pub fn build_router(config: Arc<Config>, metrics: Arc<Metrics>) -> Router {
+pub async fn build_router(config: Arc<Config>, metrics: Arc<Metrics>) -> Router {
     // One shared HTTP client for upstream requests to reuse connections.
     let client = Client::builder()
         .timeout(config.request_timeout)
         .build()
         .expect("failed to build http client");
 
-    // In-memory cache keyed by normalized request hash.
-    let cache = Arc::new(Cache::new(config.cache_ttl));
+    // Cache keyed by normalized request hash, bounded by entries and bytes.
+    let cache = open_cache(
+        config.cache_limits,
+        config.cache_stale_grace,
+        config.cache_compress_min_bytes,
+        &config.cache_backend,
+    );
+    tracing::info!(backend = config.cache_backend.name(), "configured cache");
+    // Upstream endpoints shared by every request for load balancing.
+    let upstreams = Arc::new(UpstreamPools::new(
+        &config.upstream_pools,
+        config.upstream_strategy,
+        config.circuit_breaker.clone(),
+    ));
+    tracing::info!(
+        pools = config.upstream_pools.len(),
+        routes = config.routes.rules().len(),
+        strategy = %upstreams.strategy(),
+        "configured upstreams"
+    );
     // Log buffer + broadcaster for dashboard SSE.
     let log_state = Arc::new(LogState::new(1500, 1024));
+    // Account reads are tied to account changes instead of only their TTL.
+    let account_watcher = match &config.quicknode_ws_url {
+        Some(ws_url) if config.account_invalidation => Some(AccountWatcher::spawn(
+            cache.clone(),
+            log_state.clone(),
+            ws_url.clone(),
+            config.account_watch_limit,
+        )),
+        _ => None,
+    };
+
+    let warmer = config
+        .cache_warming
+        .is_enabled()
+        .then(|| Arc::new(CacheWarmer::new(config.cache_warming.clone())));
 
     let state = AppState {
         config,
         cache,
+        upstreams,
+        flights: SingleFlight::new(),
+        refresh_backoff: RefreshBackoff::new(),
         metrics,
         log_state,
+        account_watcher,
+        warmer,
         client,
     };
 
+    // A snapshot from the previous instance is loaded before the first request.
+    if let Some(path) = &state.config.cache_snapshot_file {
+        import_snapshot_file(&state, path).await;
+    }
+
+    // Background prober keeps circuit state fresh even without traffic.
+    if let Some(every) = state.config.health_check_interval {
+        tokio::spawn(run_health_checks(state.clone(), every));
+    }
+    // Expired entries are purged even if their keys are never read again.
+    if let Some(every) = state.config.cache_sweep_interval {
+        tokio::spawn(run_sweeper(state.cache.clone(), every));
+    }
+    // Hot requests are refreshed before they expire instead of on a client miss.
+    if state.warmer.is_some() {
+        tokio::spawn(run_cache_warming(state.clone()));
+    }
+    // Slot-sensitive entries follow chain progress instead of only their TTL.
+    if let Some(ws_url) = state.config.quicknode_ws_url.clone() {
+        if state.config.slot_invalidation {
+            tokio::spawn(run_slot_invalidation(
+                state.cache.clone(),
+                state.log_state.clone(),
+                ws_url,
+            ));
+        }
+    }
+
     // Main API routes plus optional dashboard assets.
-    Router::new()
+    let mut router = Router::new()
         .route("/", post(rpc_handler))
         .route("/ws", get(ws_handler))
         .route("/health", get(health_handler))
         .route("/metrics", get(metrics_handler))
-        .merge(dashboard_routes())
-        .with_state(state)
+        .merge(dashboard_routes());
+    // Cache administration only exists once a token protects it.
+    if state.config.admin_token.is_some() {
+        router = router.merge(admin_routes(state.clone()));
+    }
+    router.with_state(state)
 }
 
 async fn health_handler() -> Json<serde_json::Value> {
@@ -60,17 +152,38 @@ async fn health_handler() -> Json<serde_json::Value> {
 }
 
 async fn metrics_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
-    Json(state.metrics.snapshot().await)
+    let mut snapshot = state.metrics.snapshot(state.cache.stats().await).await;
+    snapshot["upstreams"] = state.upstreams.snapshot();
+    Json(snapshot)
 }
 
 async fn rpc_handler(
     State(state): State<AppState>,
-    Json(payload): Json<serde_json::Value>,
-) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
-    handle_rpc_request(state, payload)
-        .await
-        .map(Json)
-        .map_err(|err| (StatusCode::BAD_GATEWAY, Json(json!({ "error": err }))))
+    payload: Result<Json<serde_json::Value>, JsonRejection>,
+) -> Response {
+    // Failures are reported as JSON-RPC error objects so clients can parse them.
+    let Json(payload) = match payload {
+        Ok(payload) => payload,
+        Err(rejection) => {
+            let err = GatewayError::Parse(rejection.body_text());
+            return Json(err.to_rpc_response(serde_json::Value::Null)).into_response();
+        }
+    };
+
+    let id = payload
+        .get("id")
+        .cloned()
+        .unwrap_or(serde_json::Value::Null);
+    match handle_rpc_request(state, payload).await {
+        Ok(RpcResponse::Json(response)) => Json(response).into_response(),
+        Ok(RpcResponse::Stream(body)) => (
+            [(CONTENT_TYPE, "application/json")],
+            Body::from_stream(body),
+        )
+            .into_response(),
+        Ok(RpcResponse::Empty) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => Json(err.to_rpc_response(id)).into_response(),
+    }
 }
 
 async fn ws_handler(
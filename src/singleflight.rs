@@ -0,0 +1,146 @@
+//! In-flight request coalescing keyed by request hash.
+
+use crate::error::GatewayError;
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+type FlightResult = Result<Value, GatewayError>;
+type Flight = Shared<BoxFuture<'static, FlightResult>>;
+
+#[derive(Clone, Default)]
+pub struct SingleFlight {
+    inflight: Arc<Mutex<HashMap<String, Flight>>>,
+}
+
+impl SingleFlight {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the future built by `work` unless an identical call is already in
+    /// flight, in which case its result is shared. Returns the result and
+    /// whether it was coalesced.
+    pub async fn run<W, F>(&self, key: &str, work: W) -> (FlightResult, bool)
+    where
+        W: FnOnce() -> F,
+        F: Future<Output = FlightResult> + Send + 'static,
+    {
+        let (flight, coalesced) = self.join(key, work);
+        (flight.await, coalesced)
+    }
+
+    /// Starts `work` in the background unless an identical call is already in
+    /// flight; returns whether it was started. Later `run` calls join it.
+    pub fn spawn<W, F>(&self, key: &str, work: W) -> bool
+    where
+        W: FnOnce() -> F,
+        F: Future<Output = FlightResult> + Send + 'static,
+    {
+        let (flight, coalesced) = self.join(key, work);
+        if !coalesced {
+            tokio::spawn(flight);
+        }
+        !coalesced
+    }
+
+    fn join<W, F>(&self, key: &str, work: W) -> (Flight, bool)
+    where
+        W: FnOnce() -> F,
+        F: Future<Output = FlightResult> + Send + 'static,
+    {
+        let mut guard = self.inflight.lock().expect("singleflight lock");
+        match guard.get(key) {
+            Some(flight) => (flight.clone(), true),
+            None => {
+                // The flight unregisters itself on completion, so it does not matter
+                // which waiter ends up driving it or whether the leader goes away.
+                let inflight = self.inflight.clone();
+                let owned_key = key.to_string();
+                let work = work();
+                let flight = async move {
+                    let result = work.await;
+                    inflight
+                        .lock()
+                        .expect("singleflight lock")
+                        .remove(&owned_key);
+                    result
+                }
+                .boxed()
+                .shared();
+                guard.insert(key.to_string(), flight.clone());
+                (flight, false)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_calls_share_one_execution() {
+        let flights = SingleFlight::new();
+        let executions = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let flights = flights.clone();
+                let executions = executions.clone();
+                tokio::spawn(async move {
+                    flights
+                        .run("hash", || async move {
+                            executions.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            Ok(Value::from(42))
+                        })
+                        .await
+                })
+            })
+            .collect();
+
+        let mut coalesced = 0;
+        for task in tasks {
+            let (result, shared) = task.await.unwrap();
+            assert_eq!(result, Ok(Value::from(42)));
+            coalesced += shared as usize;
+        }
+
+        assert_eq!(executions.load(Ordering::SeqCst), 1);
+        assert_eq!(coalesced, 7);
+    }
+
+    #[tokio::test]
+    async fn test_completed_flight_is_not_reused() {
+        let flights = SingleFlight::new();
+
+        let (first, _) = flights.run("hash", || async { Ok(Value::from(1)) }).await;
+        let (second, coalesced) = flights.run("hash", || async { Ok(Value::from(2)) }).await;
+
+        assert_eq!(first, Ok(Value::from(1)));
+        assert_eq!(second, Ok(Value::from(2)));
+        assert!(!coalesced);
+    }
+
+    #[tokio::test]
+    async fn test_spawned_flight_is_joined_by_later_calls() {
+        let flights = SingleFlight::new();
+
+        let started = flights.spawn("hash", || async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(Value::from(1))
+        });
+        let again = flights.spawn("hash", || async { Ok(Value::from(2)) });
+        let (result, coalesced) = flights.run("hash", || async { Ok(Value::from(3)) }).await;
+
+        assert!(started);
+        assert!(!again);
+        assert_eq!(result, Ok(Value::from(1)));
+        assert!(coalesced);
+    }
+}
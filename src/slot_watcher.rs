@@ -0,0 +1,191 @@
+//! Slot-driven cache invalidation over an upstream `slotSubscribe` stream.
+
+use crate::cache::Cache;
+use crate::config::Config;
+use crate::log_events::{LogEvent, LogState};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::sleep;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Cached methods whose answers change with every new slot.
+const SLOT_SENSITIVE_METHODS: [&str; 2] = ["getSlot", "getLatestBlockhash"];
+
+/// Whether slot invalidation runs under `config` and drops `method`'s entries
+/// on every new slot.
+pub fn is_invalidated_every_slot(config: &Config, method: &str) -> bool {
+    config.slot_invalidation
+        && config.quicknode_ws_url.is_some()
+        && SLOT_SENSITIVE_METHODS.contains(&method)
+}
+
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Holds a `slotSubscribe` on the upstream and drops slot-sensitive cache
+/// entries whenever the slot advances, reconnecting with backoff.
+pub async fn run_slot_invalidation(
+    cache: Arc<dyn Cache>,
+    log_state: Arc<LogState>,
+    ws_url: String,
+) {
+    let mut reconnect_delay = MIN_RECONNECT_DELAY;
+
+    loop {
+        let reason = watch_slots(cache.as_ref(), &log_state, &ws_url, &mut reconnect_delay).await;
+
+        // Entries cached while the stream was down are not tied to chain progress.
+        cache.invalidate_methods(&SLOT_SENSITIVE_METHODS).await;
+        tracing::warn!(reason = %reason, "slot subscription lost");
+        log_state
+            .record(LogEvent::new("WARN", "SLOT_STREAM_LOST").with_note(reason))
+            .await;
+
+        sleep(reconnect_delay).await;
+        reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+/// Runs one subscription until it ends; returns why it ended.
+async fn watch_slots(
+    cache: &dyn Cache,
+    log_state: &LogState,
+    ws_url: &str,
+    reconnect_delay: &mut Duration,
+) -> String {
+    let (mut socket, _) = match connect_async(ws_url).await {
+        Ok(connection) => connection,
+        // Connection errors can echo the URL, which carries the QuickNode token.
+        Err(_) => return "failed to connect to upstream websocket".to_string(),
+    };
+
+    let subscribe = json!({ "jsonrpc": "2.0", "id": 1, "method": "slotSubscribe" });
+    if socket
+        .send(Message::Text(subscribe.to_string()))
+        .await
+        .is_err()
+    {
+        return "failed to send slotSubscribe".to_string();
+    }
+
+    let mut last_slot = 0;
+    while let Some(message) = socket.next().await {
+        let text = match message {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) => return "upstream closed the websocket".to_string(),
+            Ok(_) => continue,
+            Err(err) => return format!("websocket error: {}", err),
+        };
+        let Ok(message) = serde_json::from_str::<Value>(&text) else {
+            continue;
+        };
+
+        if message.get("id").is_some() {
+            if let Some(error) = message.get("error") {
+                return format!("slotSubscribe rejected: {}", error);
+            }
+            *reconnect_delay = MIN_RECONNECT_DELAY;
+            tracing::info!("slot subscription established");
+            log_state
+                .record(LogEvent::new("INFO", "SLOT_SUBSCRIBED"))
+                .await;
+            continue;
+        }
+
+        let slot = message
+            .pointer("/params/result/slot")
+            .and_then(Value::as_u64);
+        if let Some(slot) = slot.filter(|&slot| slot > last_slot) {
+            last_slot = slot;
+            let removed = cache.invalidate_methods(&SLOT_SENSITIVE_METHODS).await;
+            tracing::debug!(slot, removed, "slot advanced");
+        }
+    }
+
+    "slot stream ended".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{CacheLimits, MemoryCache};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    /// Fake upstream that confirms the subscription and then reports `slots`.
+    async fn spawn_slot_upstream(slots: Vec<u64>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = accept_async(stream).await.unwrap();
+            socket.next().await.unwrap().unwrap();
+            let confirm = json!({ "jsonrpc": "2.0", "id": 1, "result": 7 });
+            socket
+                .send(Message::Text(confirm.to_string()))
+                .await
+                .unwrap();
+            for slot in slots {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let notification = json!({
+                    "jsonrpc": "2.0",
+                    "method": "slotNotification",
+                    "params": { "result": { "parent": slot - 1, "root": slot - 32, "slot": slot }, "subscription": 7 }
+                });
+                socket
+                    .send(Message::Text(notification.to_string()))
+                    .await
+                    .unwrap();
+            }
+            // Keep the connection open so the watcher does not reconnect.
+            std::future::pending::<()>().await;
+        });
+        format!("ws://{}", addr)
+    }
+
+    #[test]
+    fn test_slot_sensitive_methods_are_cacheable() {
+        for method in SLOT_SENSITIVE_METHODS {
+            assert!(
+                crate::privacy_mode::is_cacheable_method(method),
+                "{}",
+                method
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_new_slot_invalidates_slot_sensitive_entries() {
+        let cache = Arc::new(MemoryCache::new(CacheLimits {
+            max_entries: 100,
+            max_bytes: usize::MAX,
+        }));
+        let log_state = Arc::new(LogState::new(100, 16));
+        cache
+            .insert("slot".to_string(), "getSlot", json!(100), TTL)
+            .await;
+        cache
+            .insert("balance".to_string(), "getBalance", json!(5), TTL)
+            .await;
+
+        let url = spawn_slot_upstream(vec![101]).await;
+        tokio::spawn(run_slot_invalidation(cache.clone(), log_state.clone(), url));
+
+        for _ in 0..50 {
+            if cache.get("slot").await.is_none() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(cache.get("slot").await, None);
+        assert_eq!(cache.get("balance").await, Some(json!(5)));
+
+        let events = log_state.recent(10).await;
+        assert!(events.iter().any(|event| event.event == "SLOT_SUBSCRIBED"));
+    }
+}
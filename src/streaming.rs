@@ -0,0 +1,150 @@
+//! Byte passthrough of upstream response bodies with a size limit.
+
+use crate::error::GatewayError;
+use axum::body::Bytes;
+use futures_util::stream::{BoxStream, Stream, StreamExt};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// How a streamed body ended, reported once per stream.
+#[derive(Debug, PartialEq, Eq)]
+pub enum StreamOutcome {
+    /// Every byte was relayed.
+    Complete(u64),
+    /// Upstream failed mid-body or the body outgrew the limit.
+    Failed(GatewayError),
+    /// The client went away after this many bytes.
+    Abandoned(u64),
+}
+
+type OnFinish = Box<dyn FnOnce(StreamOutcome) + Send>;
+
+/// Relays upstream chunks unchanged, counting bytes against `limit`.
+pub struct ResponseStream {
+    inner: BoxStream<'static, Result<Bytes, GatewayError>>,
+    limit: u64,
+    received: u64,
+    on_finish: Option<OnFinish>,
+}
+
+impl ResponseStream {
+    pub fn new<S, E>(
+        inner: S,
+        limit: u64,
+        on_finish: impl FnOnce(StreamOutcome) + Send + 'static,
+    ) -> Self
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+        E: Into<GatewayError>,
+    {
+        Self {
+            inner: inner.map(|chunk| chunk.map_err(Into::into)).boxed(),
+            limit,
+            received: 0,
+            on_finish: Some(Box::new(on_finish)),
+        }
+    }
+
+    fn finish(&mut self, outcome: StreamOutcome) {
+        if let Some(on_finish) = self.on_finish.take() {
+            on_finish(outcome);
+        }
+    }
+}
+
+impl Stream for ResponseStream {
+    type Item = Result<Bytes, GatewayError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.on_finish.is_none() {
+            return Poll::Ready(None);
+        }
+
+        match self.inner.poll_next_unpin(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Some(Ok(chunk))) => {
+                self.received += chunk.len() as u64;
+                if self.received > self.limit {
+                    // Headers are already out; erroring aborts the body so the
+                    // client sees a truncated response rather than a silent cut.
+                    let err = GatewayError::ResponseTooLarge(self.limit);
+                    self.finish(StreamOutcome::Failed(err.clone()));
+                    return Poll::Ready(Some(Err(err)));
+                }
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Ready(Some(Err(err))) => {
+                self.finish(StreamOutcome::Failed(err.clone()));
+                Poll::Ready(Some(Err(err)))
+            }
+            Poll::Ready(None) => {
+                let received = self.received;
+                self.finish(StreamOutcome::Complete(received));
+                Poll::Ready(None)
+            }
+        }
+    }
+}
+
+impl Drop for ResponseStream {
+    fn drop(&mut self) {
+        let received = self.received;
+        self.finish(StreamOutcome::Abandoned(received));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream;
+    use std::sync::{Arc, Mutex};
+
+    fn chunks(parts: &[&'static str]) -> impl Stream<Item = Result<Bytes, GatewayError>> {
+        stream::iter(
+            parts
+                .iter()
+                .map(|part| Ok(Bytes::from_static(part.as_bytes())))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn recorder() -> (
+        Arc<Mutex<Vec<StreamOutcome>>>,
+        impl FnOnce(StreamOutcome) + Send,
+    ) {
+        let outcomes = Arc::new(Mutex::new(Vec::new()));
+        let sink = outcomes.clone();
+        (outcomes, move |outcome| sink.lock().unwrap().push(outcome))
+    }
+
+    #[tokio::test]
+    async fn test_relays_chunks_and_reports_size() {
+        let (outcomes, on_finish) = recorder();
+        let body = ResponseStream::new(chunks(&["{\"result\":", "1}"]), 64, on_finish);
+
+        let relayed: Vec<_> = body.collect().await;
+
+        assert_eq!(relayed.len(), 2);
+        assert_eq!(*outcomes.lock().unwrap(), vec![StreamOutcome::Complete(12)]);
+    }
+
+    #[tokio::test]
+    async fn test_aborts_once_limit_is_exceeded() {
+        let (outcomes, on_finish) = recorder();
+        let mut body = ResponseStream::new(chunks(&["12345", "67890", "tail"]), 8, on_finish);
+
+        assert!(body.next().await.unwrap().is_ok());
+        assert_eq!(
+            body.next().await.unwrap(),
+            Err(GatewayError::ResponseTooLarge(8))
+        );
+        assert!(body.next().await.is_none());
+        drop(body);
+
+        let outcomes = outcomes.lock().unwrap();
+        assert_eq!(
+            *outcomes,
+            vec![StreamOutcome::Failed(GatewayError::ResponseTooLarge(8))]
+        );
+    }
+}
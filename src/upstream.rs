@@ -0,0 +1,377 @@
+//! Upstream RPC endpoints and load-balancing strategies.
+
+use crate::circuit_breaker::{CircuitBreaker, CircuitBreakerConfig};
+use crate::routing::DEFAULT_POOL;
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Weight of the newest sample in the latency moving average, out of 10.
+const LATENCY_EWMA_WEIGHT: u64 = 3;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct UpstreamConfig {
+    pub url: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+impl UpstreamConfig {
+    pub fn from_url(url: String) -> Self {
+        Self {
+            url,
+            name: None,
+            weight: default_weight(),
+            labels: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectionStrategy {
+    RoundRobin,
+    Weighted,
+    LeastLatency,
+    Random,
+}
+
+impl FromStr for SelectionStrategy {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().replace('_', "-").as_str() {
+            "round-robin" => Ok(SelectionStrategy::RoundRobin),
+            "weighted" => Ok(SelectionStrategy::Weighted),
+            "least-latency" => Ok(SelectionStrategy::LeastLatency),
+            "random" => Ok(SelectionStrategy::Random),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for SelectionStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = match self {
+            SelectionStrategy::RoundRobin => "round-robin",
+            SelectionStrategy::Weighted => "weighted",
+            SelectionStrategy::LeastLatency => "least-latency",
+            SelectionStrategy::Random => "random",
+        };
+        write!(f, "{}", value)
+    }
+}
+
+pub struct Upstream {
+    pub name: String,
+    pub url: String,
+    pub weight: u32,
+    pub labels: Vec<String>,
+    pub breaker: CircuitBreaker,
+    // Exponentially weighted latency in microseconds; 0 until the first sample.
+    latency_us: AtomicU64,
+}
+
+impl Upstream {
+    fn new(
+        pool: &str,
+        index: usize,
+        config: UpstreamConfig,
+        breaker: CircuitBreakerConfig,
+    ) -> Self {
+        // Names are used in logs and events, so never fall back to the URL (it may hold a token).
+        let name = config.name.unwrap_or_else(|| format!("{}-{}", pool, index));
+        Self {
+            name,
+            url: config.url,
+            weight: config.weight,
+            labels: config.labels,
+            breaker: CircuitBreaker::new(breaker),
+            latency_us: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_latency(&self, elapsed: Duration) {
+        let sample = elapsed.as_micros().min(u64::MAX as u128) as u64;
+        // Races between concurrent updates only lose a sample, which is fine for an average.
+        let previous = self.latency_us.load(Ordering::Relaxed);
+        let next = if previous == 0 {
+            sample.max(1)
+        } else {
+            (sample * LATENCY_EWMA_WEIGHT + previous * (10 - LATENCY_EWMA_WEIGHT)) / 10
+        };
+        self.latency_us.store(next.max(1), Ordering::Relaxed);
+    }
+
+    pub fn latency(&self) -> Duration {
+        Duration::from_micros(self.latency_us.load(Ordering::Relaxed))
+    }
+}
+
+pub struct UpstreamPool {
+    strategy: SelectionStrategy,
+    upstreams: Vec<Upstream>,
+    cursor: AtomicUsize,
+}
+
+impl UpstreamPool {
+    pub fn new(
+        pool: &str,
+        configs: Vec<UpstreamConfig>,
+        strategy: SelectionStrategy,
+        breaker: CircuitBreakerConfig,
+    ) -> Self {
+        let upstreams = configs
+            .into_iter()
+            .enumerate()
+            .map(|(index, config)| Upstream::new(pool, index, config, breaker.clone()))
+            .collect();
+        Self {
+            strategy,
+            upstreams,
+            cursor: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> &Upstream {
+        &self.upstreams[index]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Upstream> {
+        self.upstreams.iter()
+    }
+
+    pub fn snapshot(&self) -> Value {
+        // URLs are left out on purpose; they usually embed the provider token.
+        let upstreams: Vec<Value> = self
+            .upstreams
+            .iter()
+            .map(|upstream| {
+                json!({
+                    "name": upstream.name,
+                    "weight": upstream.weight,
+                    "labels": upstream.labels,
+                    "circuit": upstream.breaker.state().to_string(),
+                    "latency_ms": upstream.latency().as_secs_f64() * 1000.0
+                })
+            })
+            .collect();
+
+        Value::Array(upstreams)
+    }
+
+    /// Picks an upstream index, preferring ones not in `tried` so retries move elsewhere.
+    ///
+    /// Upstreams with an open circuit are skipped; `None` means nothing is available.
+    pub fn select(&self, tried: &[usize]) -> Option<usize> {
+        let available: Vec<usize> = (0..self.upstreams.len())
+            .filter(|&index| self.upstreams[index].breaker.is_available())
+            .collect();
+        let mut candidates: Vec<usize> = available
+            .iter()
+            .copied()
+            .filter(|index| !tried.contains(index))
+            .collect();
+        if candidates.is_empty() {
+            // Every available upstream was tried once; start over rather than failing early.
+            candidates = available;
+        }
+
+        while !candidates.is_empty() {
+            let index = match candidates.len() {
+                1 => candidates[0],
+                _ => self.pick(&candidates),
+            };
+            // Another request may have claimed a half-open trial in the meantime.
+            if self.upstreams[index].breaker.try_acquire() {
+                return Some(index);
+            }
+            candidates.retain(|&candidate| candidate != index);
+        }
+
+        None
+    }
+
+    fn pick(&self, candidates: &[usize]) -> usize {
+        match self.strategy {
+            SelectionStrategy::RoundRobin => {
+                let next = self.cursor.fetch_add(1, Ordering::Relaxed);
+                candidates[next % candidates.len()]
+            }
+            SelectionStrategy::Weighted => {
+                let total: u64 = candidates
+                    .iter()
+                    .map(|&index| self.upstreams[index].weight as u64)
+                    .sum();
+                if total == 0 {
+                    return candidates[0];
+                }
+                let mut point = rand::thread_rng().gen_range(0..total);
+                for &index in candidates {
+                    let weight = self.upstreams[index].weight as u64;
+                    if point < weight {
+                        return index;
+                    }
+                    point -= weight;
+                }
+                candidates[candidates.len() - 1]
+            }
+            SelectionStrategy::LeastLatency => {
+                // Unmeasured upstreams report zero latency, so each gets probed first.
+                *candidates
+                    .iter()
+                    .min_by_key(|&&index| self.upstreams[index].latency())
+                    .expect("non-empty candidates")
+            }
+            SelectionStrategy::Random => {
+                candidates[rand::thread_rng().gen_range(0..candidates.len())]
+            }
+        }
+    }
+}
+
+/// Named upstream pools that routing rules can target.
+pub struct UpstreamPools {
+    strategy: SelectionStrategy,
+    pools: BTreeMap<String, UpstreamPool>,
+}
+
+impl UpstreamPools {
+    pub fn new(
+        configs: &HashMap<String, Vec<UpstreamConfig>>,
+        strategy: SelectionStrategy,
+        breaker: CircuitBreakerConfig,
+    ) -> Self {
+        let pools = configs
+            .iter()
+            .map(|(name, upstreams)| {
+                let pool = UpstreamPool::new(name, upstreams.clone(), strategy, breaker.clone());
+                (name.clone(), pool)
+            })
+            .collect();
+        Self { strategy, pools }
+    }
+
+    /// Looks up a pool, falling back to the default pool for unknown names.
+    pub fn get(&self, name: &str) -> &UpstreamPool {
+        self.pools
+            .get(name)
+            .or_else(|| self.pools.get(DEFAULT_POOL))
+            .expect("default upstream pool is configured")
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &UpstreamPool)> {
+        self.pools.iter().map(|(name, pool)| (name.as_str(), pool))
+    }
+
+    pub fn strategy(&self) -> SelectionStrategy {
+        self.strategy
+    }
+
+    pub fn snapshot(&self) -> Value {
+        let pools: Map<String, Value> = self
+            .pools
+            .iter()
+            .map(|(name, pool)| (name.clone(), pool.snapshot()))
+            .collect();
+
+        json!({
+            "strategy": self.strategy.to_string(),
+            "pools": pools
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(weights: &[u32], strategy: SelectionStrategy) -> UpstreamPool {
+        let configs = weights
+            .iter()
+            .enumerate()
+            .map(|(index, &weight)| UpstreamConfig {
+                weight,
+                ..UpstreamConfig::from_url(format!("http://upstream-{}", index))
+            })
+            .collect();
+        UpstreamPool::new("test", configs, strategy, CircuitBreakerConfig::default())
+    }
+
+    #[test]
+    fn test_round_robin_cycles_through_upstreams() {
+        let pool = pool(&[1, 1, 1], SelectionStrategy::RoundRobin);
+        let picks: Vec<usize> = (0..6).map(|_| pool.select(&[]).unwrap()).collect();
+
+        assert_eq!(picks, vec![0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn test_weighted_skips_zero_weight_upstreams() {
+        let pool = pool(&[0, 5], SelectionStrategy::Weighted);
+
+        for _ in 0..100 {
+            assert_eq!(pool.select(&[]), Some(1));
+        }
+    }
+
+    #[test]
+    fn test_least_latency_prefers_fastest() {
+        let pool = pool(&[1, 1, 1], SelectionStrategy::LeastLatency);
+        pool.get(0).record_latency(Duration::from_millis(80));
+        pool.get(1).record_latency(Duration::from_millis(20));
+        pool.get(2).record_latency(Duration::from_millis(50));
+
+        assert_eq!(pool.select(&[]), Some(1));
+        assert_eq!(pool.select(&[1]), Some(2));
+    }
+
+    #[test]
+    fn test_select_avoids_tried_upstreams_until_exhausted() {
+        let pool = pool(&[1, 1], SelectionStrategy::Random);
+
+        assert_eq!(pool.select(&[0]), Some(1));
+        assert!(pool.select(&[0, 1]).is_some());
+    }
+
+    #[test]
+    fn test_select_skips_open_circuits() {
+        let pool = pool(&[1, 1], SelectionStrategy::RoundRobin);
+        for _ in 0..CircuitBreakerConfig::default().min_requests {
+            pool.get(0).breaker.record_failure();
+        }
+
+        for _ in 0..4 {
+            assert_eq!(pool.select(&[]), Some(1));
+        }
+        for _ in 0..CircuitBreakerConfig::default().min_requests {
+            pool.get(1).breaker.record_failure();
+        }
+        assert_eq!(pool.select(&[]), None);
+    }
+
+    #[test]
+    fn test_strategy_parsing() {
+        assert_eq!(
+            "round_robin".parse::<SelectionStrategy>(),
+            Ok(SelectionStrategy::RoundRobin)
+        );
+        assert_eq!(
+            "Least-Latency".parse::<SelectionStrategy>(),
+            Ok(SelectionStrategy::LeastLatency)
+        );
+        assert!("fastest".parse::<SelectionStrategy>().is_err());
+    }
+}